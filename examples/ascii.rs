@@ -0,0 +1,53 @@
+//! Runs a ROM for a few seconds without a window and prints the display as
+//! text, holding down a key from the command line.
+//!
+//!     cargo run --example ascii -- roms/ibm.ch8 [frames] [key]
+
+use std::process::exit;
+
+use chip8::{Chip8, Config, FRAME_RATE};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        println!("Usage: ascii <rom> [frames] [key]");
+        exit(1);
+    };
+    let frames = args
+        .next()
+        .map_or(Ok(3 * FRAME_RATE), |frames| frames.parse())
+        .expect("invalid frame count");
+    let key = args
+        .next()
+        .map(|key| u8::from_str_radix(&key, 16).expect("invalid key"));
+
+    let rom = std::fs::read(&path).expect("could not read the ROM");
+    let mut cpu = Chip8::with_config(&Config {
+        seed: Some(1),
+        ..Config::default()
+    });
+    cpu.load_rom(&rom).expect("could not load the ROM");
+
+    // Bit n of the keypad mask is key n
+    if let Some(key) = key {
+        cpu.set_keypad(1 << key);
+    }
+
+    for _ in 0..frames {
+        if let Err(e) = cpu.tick() {
+            println!("{}", e);
+            break;
+        }
+    }
+
+    let width = cpu.display_width();
+
+    for row in cpu.framebuffer().chunks(width) {
+        let line = row
+            .iter()
+            .map(|&pixel| if pixel == 0 { ' ' } else { '#' })
+            .collect::<String>();
+
+        println!("{}", line.trim_end());
+    }
+}
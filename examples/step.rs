@@ -0,0 +1,58 @@
+//! Steps through the start of a ROM one instruction at a time, printing
+//! each instruction and the registers it changed.
+//!
+//!     cargo run --example step -- roms/pong.ch8 [instructions]
+
+use std::process::exit;
+
+use chip8::instruction::decode;
+use chip8::Chip8;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        println!("Usage: step <rom> [instructions]");
+        exit(1);
+    };
+    let count = args
+        .next()
+        .map_or(Ok(20), |count| count.parse::<usize>())
+        .expect("invalid instruction count");
+
+    let rom = std::fs::read(&path).expect("could not read the ROM");
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&rom).expect("could not load the ROM");
+
+    for _ in 0..count {
+        let before = cpu.registers();
+        let pc = before.pc as usize;
+        let opcode = u16::from_be_bytes([cpu.memory()[pc], cpu.memory()[pc + 1]]);
+
+        if let Err(e) = cpu.step() {
+            println!("{}", e);
+            break;
+        }
+
+        let after = cpu.registers();
+        let mut changes = vec![];
+
+        for (i, (old, new)) in before.v.iter().zip(after.v).enumerate() {
+            if *old != new {
+                changes.push(format!("V{:X}={:02X}", i, new));
+            }
+        }
+
+        if before.i != after.i {
+            changes.push(format!("I={:04X}", after.i));
+        }
+
+        let line = format!(
+            "{:04X}  {:04X}  {:<20}  {}",
+            pc,
+            opcode,
+            decode(opcode).to_string(),
+            changes.join(" ")
+        );
+        println!("{}", line.trim_end());
+    }
+}
@@ -0,0 +1,47 @@
+use chip8::Chip8;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+const BEEP_FREQUENCY: f32 = 880.0;
+const PATTERN_BITS: f32 = 128.0;
+const VOLUME: f32 = 0.25;
+
+/// Turns the sound timer, or the XO-CHIP audio pattern, into interleaved
+/// stereo samples one frame at a time.
+#[derive(Debug, Default)]
+pub struct Beeper {
+    phase: f32,
+    position: f32,
+}
+
+impl Beeper {
+    pub fn frame(&mut self, chip8: &Chip8, xo_chip: bool, samples: &mut Vec<i16>) {
+        let frames = (SAMPLE_RATE / chip8::FRAME_RATE) as usize;
+        let beeping = chip8.sound_timer() > 0 && !chip8.halted();
+        let pattern = chip8.audio_pattern();
+        let step = chip8.playback_rate() / SAMPLE_RATE as f32;
+        let amplitude = (VOLUME * i16::MAX as f32) as i16;
+
+        samples.clear();
+
+        for _ in 0..frames {
+            let on = if xo_chip {
+                let bit = self.position as usize;
+                self.position = (self.position + step) % PATTERN_BITS;
+                pattern[bit / 8] >> (7 - bit % 8) & 1 != 0
+            } else {
+                let on = self.phase <= 0.5;
+                self.phase = (self.phase + BEEP_FREQUENCY / SAMPLE_RATE as f32) % 1.0;
+                on
+            };
+
+            let sample = match (beeping, on) {
+                (false, _) => 0,
+                (true, true) => amplitude,
+                (true, false) => -amplitude,
+            };
+
+            samples.extend([sample, sample]);
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! libretro core for the CHIP-8 emulator, built as a shared library that
+//! frontends such as RetroArch can load.
+//!
+//! The 16-key keypad is mapped both to the RetroPad of port 0 (the D-pad on
+//! 2, 4, 6 and 8 and the face buttons on 5, 0, 1 and 3) and to the hex digit
+//! keys of the keyboard, as in the SDL window. ROMs ending in `.sc8` run with
+//! SUPER-CHIP quirks and ROMs ending in `.xo8` as XO-CHIP programs.
+
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chip8::{
+    Chip8, Config, Quirks, FRAME_RATE, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH,
+};
+
+pub mod api;
+mod audio;
+
+use api::*;
+use audio::{Beeper, SAMPLE_RATE};
+
+const PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xFF6600, 0x662200];
+
+/// RetroPad button, keypad key and the name the frontend shows for it.
+const JOYPAD_KEYS: [(c_uint, u8, &CStr); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2, c"Up (2)"),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8, c"Down (8)"),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4, c"Left (4)"),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6, c"Right (6)"),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5, c"5"),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0, c"0"),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x1, c"1"),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x3, c"3"),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x7, c"7"),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x9, c"9"),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xA, c"A"),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xB, c"B"),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0xC, c"C"),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0xD, c"D"),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xE, c"E"),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xF, c"F"),
+];
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+struct Core {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    xo_chip: bool,
+    keypad: u16,
+    beeper: Beeper,
+    video: Vec<u32>,
+    samples: Vec<i16>,
+}
+
+impl Core {
+    fn new(rom: Vec<u8>, path: Option<&Path>) -> Option<Core> {
+        let extension = path
+            .and_then(|path| path.extension())
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let (quirks, xo_chip) = match extension.as_deref() {
+            Some("sc8") => (Quirks::SUPER_CHIP, false),
+            Some("xo8") => (Quirks::XO_CHIP, true),
+            _ => (Quirks::COSMAC_VIP, false),
+        };
+
+        let mut chip8 = Chip8::with_config(&Config {
+            quirks,
+            xo_chip,
+            ..Config::default()
+        });
+        chip8.load_rom(&rom).ok()?;
+
+        Some(Core {
+            chip8,
+            rom,
+            xo_chip,
+            keypad: 0,
+            beeper: Beeper::default(),
+            video: vec![0; HIRES_WIDTH * HIRES_HEIGHT],
+            samples: vec![],
+        })
+    }
+
+    fn run(&mut self, callbacks: Callbacks) {
+        if let Some(input_poll) = callbacks.input_poll {
+            unsafe { input_poll() };
+        }
+
+        if let Some(input_state) = callbacks.input_state {
+            let keypad = read_keypad(input_state);
+
+            // Só repassa mudanças, como os eventos de tecla das outras interfaces
+            if keypad != self.keypad {
+                self.chip8.set_keypad(keypad);
+                self.keypad = keypad;
+            }
+        }
+
+        if let Err(e) = self.chip8.tick() {
+            eprintln!("{}", e);
+            self.chip8.halt();
+        }
+
+        let width = self.chip8.display_width();
+        let height = self.chip8.display_height();
+
+        for (pixel, color) in self.video.iter_mut().zip(self.chip8.framebuffer()) {
+            *pixel = PALETTE[color as usize];
+        }
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            unsafe {
+                video_refresh(
+                    self.video.as_ptr().cast(),
+                    width as c_uint,
+                    height as c_uint,
+                    width * size_of::<u32>(),
+                )
+            };
+        }
+
+        self.beeper
+            .frame(&self.chip8, self.xo_chip, &mut self.samples);
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let mut sent = 0;
+
+            // O frontend pode aceitar só parte das amostras de cada vez
+            while sent < self.samples.len() {
+                let frames = (self.samples.len() - sent) / 2;
+                let accepted = unsafe { audio_sample_batch(self.samples[sent..].as_ptr(), frames) };
+
+                if accepted == 0 {
+                    break;
+                }
+                sent += accepted * 2;
+            }
+        }
+    }
+}
+
+fn read_keypad(input_state: InputStateFn) -> u16 {
+    let mut keypad = 0;
+
+    for (id, key, _) in JOYPAD_KEYS {
+        if unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, id) } != 0 {
+            keypad |= 1 << key;
+        }
+    }
+
+    for key in 0..16 {
+        let code = if key < 10 {
+            RETROK_0 + key
+        } else {
+            RETROK_A + key - 10
+        };
+
+        if unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, code) } != 0 {
+            keypad |= 1 << key;
+        }
+    }
+
+    keypad
+}
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match CALLBACKS.lock().unwrap().environment {
+        Some(environment) => unsafe { environment(cmd, data) },
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+/// Samples are always sent in batches, so the single sample callback is
+/// never used.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.lock().unwrap().take();
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `SystemInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    const VERSION: &CStr =
+        match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+            Ok(version) => version,
+            Err(_) => panic!("the version has no NUL bytes"),
+        };
+
+    info.write(SystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: VERSION.as_ptr(),
+        valid_extensions: c"ch8|sc8|xo8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    });
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `SystemAvInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    info.write(SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: LORES_WIDTH as c_uint,
+            base_height: LORES_HEIGHT as c_uint,
+            max_width: HIRES_WIDTH as c_uint,
+            max_height: HIRES_HEIGHT as c_uint,
+            aspect_ratio: 2.0,
+        },
+        timing: SystemTiming {
+            fps: FRAME_RATE as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.chip8.load_rom(&core.rom).ok();
+        core.keypad = 0;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = *CALLBACKS.lock().unwrap();
+
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.run(callbacks);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) => core.chip8.save_state().len(),
+        None => 0,
+    }
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let Some(state) = CORE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|core| core.chip8.save_state())
+    else {
+        return false;
+    };
+
+    if data.is_null() || size < state.len() {
+        return false;
+    }
+
+    std::ptr::copy_nonoverlapping(state.as_ptr(), data.cast(), state.len());
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let state = std::slice::from_raw_parts(data.cast::<u8>(), size);
+
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) => core.chip8.load_state(state).is_ok(),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must be null or point to a valid `GameInfo` whose `data` holds
+/// `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+
+    if game.data.is_null() {
+        return false;
+    }
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, (&raw mut format).cast()) {
+        return false;
+    }
+
+    let mut descriptors = JOYPAD_KEYS
+        .iter()
+        .map(|&(id, _, description)| InputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id,
+            description: description.as_ptr(),
+        })
+        .collect::<Vec<_>>();
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: std::ptr::null(),
+    });
+    environment(
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr().cast(),
+    );
+
+    let rom = std::slice::from_raw_parts(game.data.cast::<u8>(), game.size).to_vec();
+    let path = (!game.path.is_null())
+        .then(|| CStr::from_ptr(game.path).to_str().ok())
+        .flatten()
+        .map(Path::new);
+
+    let core = Core::new(rom, path);
+    let loaded = core.is_some();
+    *CORE.lock().unwrap() = core;
+
+    loaded
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.lock().unwrap().take();
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// Exposes the machine's memory as system RAM, for cheats and achievements.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.chip8.memory_mut().as_mut_ptr().cast(),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.chip8.memory().len(),
+        _ => 0,
+    }
+}
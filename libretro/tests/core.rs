@@ -0,0 +1,102 @@
+mod harness;
+
+use chip8_libretro::api::*;
+use harness::Core;
+
+// 0x200: LD V0, 0; LD F, V0; DRW V0, V0, 5; JP 0x206
+const DRAW_ZERO: [u8; 8] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+
+// 0x200: LD V0, K; LD I, 0x300; LD [I], V0; JP 0x206
+const STORE_KEY: [u8; 8] = [0xF0, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+
+#[test]
+fn describes_the_system() {
+    let core = Core::load(&DRAW_ZERO, "zero.ch8");
+
+    assert_eq!(
+        core.system_info(),
+        ("CHIP-8".to_string(), "ch8|sc8|xo8".to_string())
+    );
+    assert_eq!(core.pixel_format(), Some(RETRO_PIXEL_FORMAT_XRGB8888));
+
+    let info = core.av_info();
+    assert_eq!(
+        (info.geometry.base_width, info.geometry.base_height),
+        (64, 32)
+    );
+    assert_eq!(
+        (info.geometry.max_width, info.geometry.max_height),
+        (128, 64)
+    );
+    assert_eq!(info.timing.fps, 60.0);
+    assert_eq!(info.timing.sample_rate, 44100.0);
+}
+
+#[test]
+fn draws_xrgb8888_frames() {
+    let mut core = Core::load(&DRAW_ZERO, "zero.ch8");
+    let frame = core.run(1);
+
+    assert_eq!((frame.width, frame.height), (64, 32));
+    // The top row of the 0 glyph is 0xF0
+    assert_eq!(
+        (0..5).map(|x| frame.pixel(x, 0)).collect::<Vec<_>>(),
+        [0xFFFFFF, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF, 0x000000]
+    );
+    assert_eq!(frame.pixel(1, 1), 0x000000);
+}
+
+#[test]
+fn maps_retropad_and_keyboard_to_the_keypad() {
+    let mut core = Core::load(&STORE_KEY, "key.ch8");
+
+    core.run(2);
+    core.set_joypad(RETRO_DEVICE_ID_JOYPAD_A, true);
+    core.run(2);
+    core.set_joypad(RETRO_DEVICE_ID_JOYPAD_A, false);
+    core.run(2);
+    assert_eq!(core.memory()[0x300], 0x5);
+
+    core.reset();
+    core.run(2);
+    core.set_key(RETROK_A + 2, true);
+    core.run(2);
+    core.set_key(RETROK_A + 2, false);
+    core.run(2);
+    assert_eq!(core.memory()[0x300], 0xC);
+}
+
+#[test]
+fn sends_a_frame_of_audio_while_beeping() {
+    // 0x200: LD V0, 3; LD ST, V0; JP 0x204
+    let mut core = Core::load(&[0x60, 0x03, 0xF0, 0x18, 0x12, 0x04], "beep.ch8");
+
+    core.run(1);
+    let samples = core.audio();
+    assert_eq!(samples.len(), 735 * 2);
+    assert!(samples.iter().all(|&sample| sample != 0));
+
+    core.run(5);
+    core.audio();
+    core.run(1);
+    assert!(core.audio().iter().all(|&sample| sample == 0));
+}
+
+#[test]
+fn serializes_and_restores_state() {
+    // 0x200: ADD V0, 1; JP 0x200
+    let mut core = Core::load(&[0x70, 0x01, 0x12, 0x00], "count.ch8");
+
+    core.run(5);
+    let saved = core.serialize();
+    core.run(5);
+    let later = core.serialize();
+
+    assert_ne!(saved, later);
+    assert!(core.unserialize(&saved));
+    core.run(5);
+    assert_eq!(core.serialize(), later);
+
+    assert!(!core.unserialize(&saved[..saved.len() / 2]));
+    assert_eq!(core.serialize(), later);
+}
@@ -0,0 +1,300 @@
+//! A tiny libretro frontend that loads the core's shared library the way
+//! RetroArch would, runs it one frame at a time and records what it gets
+//! back.
+
+use std::ffi::{c_uint, c_void, CStr, CString};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use chip8_libretro::api::*;
+
+/// The core keeps its state in globals, so only one harness may be alive.
+static LOADED: Mutex<()> = Mutex::new(());
+
+static PIXEL_FORMAT: Mutex<Option<c_uint>> = Mutex::new(None);
+static FRAME: Mutex<Option<Frame>> = Mutex::new(None);
+static AUDIO: Mutex<Vec<i16>> = Mutex::new(vec![]);
+static JOYPAD: Mutex<u16> = Mutex::new(0);
+static KEYBOARD: Mutex<Vec<c_uint>> = Mutex::new(vec![]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Frame {
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            *PIXEL_FORMAT.lock().unwrap() = Some(*data.cast::<c_uint>());
+            true
+        }
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => true,
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let row = data.cast::<u8>().add(y * pitch).cast::<u32>();
+        pixels.extend_from_slice(std::slice::from_raw_parts(row, width));
+    }
+
+    *FRAME.lock().unwrap() = Some(Frame {
+        width,
+        height,
+        pixels,
+    });
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {
+    panic!("the core sends audio in batches");
+}
+
+unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = std::slice::from_raw_parts(data, frames * 2);
+    AUDIO.lock().unwrap().extend_from_slice(samples);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let pressed = match (port, device) {
+        (0, RETRO_DEVICE_JOYPAD) => *JOYPAD.lock().unwrap() & (1 << id) != 0,
+        (0, RETRO_DEVICE_KEYBOARD) => KEYBOARD.lock().unwrap().contains(&id),
+        _ => false,
+    };
+
+    pressed as i16
+}
+
+/// Path of the shared library Cargo built next to the test binary.
+fn library_path() -> PathBuf {
+    let name = format!(
+        "{}chip8_libretro{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    );
+    let deps = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+
+    [deps.join(&name), deps.parent().unwrap().join(&name)]
+        .into_iter()
+        .find(|path| path.exists())
+        .unwrap_or_else(|| panic!("{} has not been built", name))
+}
+
+pub struct Core {
+    library: *mut c_void,
+    _loaded: MutexGuard<'static, ()>,
+}
+
+macro_rules! symbol {
+    ($core:expr, $name:literal, $type:ty) => {{
+        let symbol = libc::dlsym($core.library, concat!($name, "\0").as_ptr().cast());
+        assert!(!symbol.is_null(), "missing {}", $name);
+        std::mem::transmute::<*mut c_void, $type>(symbol)
+    }};
+}
+
+impl Core {
+    /// Loads the library and the ROM, which is given `path` so that its
+    /// extension can pick the platform.
+    pub fn load(rom: &[u8], path: &str) -> Core {
+        let loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+
+        *PIXEL_FORMAT.lock().unwrap() = None;
+        *FRAME.lock().unwrap() = None;
+        AUDIO.lock().unwrap().clear();
+        *JOYPAD.lock().unwrap() = 0;
+        KEYBOARD.lock().unwrap().clear();
+
+        let library_path = CString::new(library_path().to_str().unwrap()).unwrap();
+        let library = unsafe { libc::dlopen(library_path.as_ptr(), libc::RTLD_NOW) };
+        assert!(!library.is_null(), "could not load the core");
+
+        let core = Core {
+            library,
+            _loaded: loaded,
+        };
+
+        unsafe {
+            assert_eq!(
+                symbol!(core, "retro_api_version", extern "C" fn() -> c_uint)(),
+                1
+            );
+
+            symbol!(core, "retro_set_environment", extern "C" fn(EnvironmentFn))(environment);
+            symbol!(
+                core,
+                "retro_set_video_refresh",
+                extern "C" fn(VideoRefreshFn)
+            )(video_refresh);
+            symbol!(core, "retro_set_audio_sample", extern "C" fn(AudioSampleFn))(audio_sample);
+            symbol!(
+                core,
+                "retro_set_audio_sample_batch",
+                extern "C" fn(AudioSampleBatchFn)
+            )(audio_sample_batch);
+            symbol!(core, "retro_set_input_poll", extern "C" fn(InputPollFn))(input_poll);
+            symbol!(core, "retro_set_input_state", extern "C" fn(InputStateFn))(input_state);
+            symbol!(core, "retro_init", extern "C" fn())();
+
+            let path = CString::new(path).unwrap();
+            let game = GameInfo {
+                path: path.as_ptr(),
+                data: rom.as_ptr().cast(),
+                size: rom.len(),
+                meta: std::ptr::null(),
+            };
+            let load_game = symbol!(
+                core,
+                "retro_load_game",
+                unsafe extern "C" fn(*const GameInfo) -> bool
+            );
+            assert!(load_game(&game), "the core rejected the ROM");
+        }
+
+        core
+    }
+
+    pub fn system_info(&self) -> (String, String) {
+        unsafe {
+            let mut info = std::mem::zeroed::<SystemInfo>();
+            symbol!(
+                self,
+                "retro_get_system_info",
+                unsafe extern "C" fn(*mut SystemInfo)
+            )(&mut info);
+
+            let name = CStr::from_ptr(info.library_name).to_str().unwrap();
+            let extensions = CStr::from_ptr(info.valid_extensions).to_str().unwrap();
+            (name.to_string(), extensions.to_string())
+        }
+    }
+
+    pub fn av_info(&self) -> SystemAvInfo {
+        unsafe {
+            let mut info = std::mem::zeroed::<SystemAvInfo>();
+            symbol!(
+                self,
+                "retro_get_system_av_info",
+                unsafe extern "C" fn(*mut SystemAvInfo)
+            )(&mut info);
+            info
+        }
+    }
+
+    pub fn pixel_format(&self) -> Option<c_uint> {
+        *PIXEL_FORMAT.lock().unwrap()
+    }
+
+    /// Runs `frames` frames, returning the last one drawn.
+    pub fn run(&mut self, frames: usize) -> Frame {
+        let run = unsafe { symbol!(self, "retro_run", extern "C" fn()) };
+
+        for _ in 0..frames {
+            run();
+        }
+
+        FRAME.lock().unwrap().clone().expect("no frame was drawn")
+    }
+
+    pub fn reset(&mut self) {
+        unsafe { symbol!(self, "retro_reset", extern "C" fn())() }
+    }
+
+    /// Takes the audio samples sent since the last call, interleaved.
+    pub fn audio(&mut self) -> Vec<i16> {
+        std::mem::take(&mut AUDIO.lock().unwrap())
+    }
+
+    pub fn set_joypad(&mut self, id: c_uint, pressed: bool) {
+        let mut joypad = JOYPAD.lock().unwrap();
+
+        if pressed {
+            *joypad |= 1 << id;
+        } else {
+            *joypad &= !(1 << id);
+        }
+    }
+
+    pub fn set_key(&mut self, code: c_uint, pressed: bool) {
+        let mut keyboard = KEYBOARD.lock().unwrap();
+        keyboard.retain(|&key| key != code);
+
+        if pressed {
+            keyboard.push(code);
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        unsafe {
+            let size = symbol!(self, "retro_serialize_size", extern "C" fn() -> usize)();
+            let mut state = vec![0; size];
+            let serialize = symbol!(
+                self,
+                "retro_serialize",
+                unsafe extern "C" fn(*mut c_void, usize) -> bool
+            );
+            assert!(serialize(state.as_mut_ptr().cast(), size));
+            state
+        }
+    }
+
+    pub fn unserialize(&mut self, state: &[u8]) -> bool {
+        unsafe {
+            symbol!(
+                self,
+                "retro_unserialize",
+                unsafe extern "C" fn(*const c_void, usize) -> bool
+            )(state.as_ptr().cast(), state.len())
+        }
+    }
+
+    /// The memory the core exposes as system RAM.
+    pub fn memory(&self) -> &[u8] {
+        unsafe {
+            let data = symbol!(
+                self,
+                "retro_get_memory_data",
+                extern "C" fn(c_uint) -> *mut c_void
+            )(RETRO_MEMORY_SYSTEM_RAM);
+            let size = symbol!(
+                self,
+                "retro_get_memory_size",
+                extern "C" fn(c_uint) -> usize
+            )(RETRO_MEMORY_SYSTEM_RAM);
+            std::slice::from_raw_parts(data.cast(), size)
+        }
+    }
+}
+
+impl Drop for Core {
+    fn drop(&mut self) {
+        unsafe {
+            symbol!(self, "retro_unload_game", extern "C" fn())();
+            symbol!(self, "retro_deinit", extern "C" fn())();
+            libc::dlclose(self.library);
+        }
+    }
+}
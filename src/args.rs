@@ -14,7 +14,7 @@ impl Args {
         let mut options = HashMap::new();
 
         let mut iter = args.iter().peekable();
-        let flag_prefix = "--";
+        let flag_prefix = '-';
         let mut parent_flag: Option<String> = None;
 
         while iter.peek().is_some() {
@@ -25,26 +25,32 @@ impl Args {
                 continue;
             }
 
-            if let Some(parent) = &parent_flag {
-                options.insert(parent.clone(), Some(arg.clone()));
-                parent_flag = None;
-                continue;
+            // Só flags curtos como `-o` recebem o valor no argumento seguinte;
+            // flags longos usam `--flag=valor`, senão um `--headless rom.ch8`
+            // engoliria a ROM
+            if let Some(parent) = parent_flag.take() {
+                if !arg.starts_with(flag_prefix) || arg.len() == 1 {
+                    options.insert(parent, Some(arg.clone()));
+                    continue;
+                }
             }
 
-            if !arg.starts_with(flag_prefix) {
+            if !arg.starts_with(flag_prefix) || arg.len() == 1 {
                 positional.push(arg.clone());
                 continue;
             }
 
-            let flag = arg.trim_start_matches(flag_prefix);
-
-            if let Some((key, value)) = flag.split_once('=') {
-                options.insert(key.to_string(), Some(value.to_string()));
+            if let Some(flag) = arg.strip_prefix("--") {
+                match flag.split_once('=') {
+                    Some((key, value)) => options.insert(key.to_string(), Some(value.to_string())),
+                    None => options.insert(flag.to_string(), None),
+                };
                 continue;
-            } else {
-                parent_flag.replace(flag.to_string());
-                options.insert(flag.to_string(), None);
             }
+
+            let flag = arg.trim_start_matches(flag_prefix);
+            parent_flag.replace(flag.to_string());
+            options.insert(flag.to_string(), None);
         }
 
         Self {
@@ -78,3 +84,43 @@ impl<T: Iterator<Item = String>> From<T> for Args {
         Self::new(iter.collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Args {
+        Args::new(line.split_whitespace().map(str::to_string).collect())
+    }
+
+    #[test]
+    fn flag_does_not_take_next_flag_as_value() {
+        let args = args("chip8 run rom.ch8 --headless --frames=10 -o out.ch8 --debug");
+
+        assert_eq!(args.positional(1).map(String::as_str), Some("rom.ch8"));
+        assert!(args.has_option("headless"));
+        assert_eq!(args.option("headless"), None);
+        assert_eq!(args.option("frames").as_deref(), Some("10"));
+        assert_eq!(args.option("o").as_deref(), Some("out.ch8"));
+        assert!(args.has_option("debug"));
+    }
+
+    #[test]
+    fn long_flag_does_not_take_next_positional_as_value() {
+        let run = args("chip8 run --headless roms/ibm.ch8 --frames=10");
+
+        assert_eq!(run.positional(0).map(String::as_str), Some("run"));
+        assert_eq!(run.positional(1).map(String::as_str), Some("roms/ibm.ch8"));
+        assert!(run.has_option("headless"));
+        assert_eq!(run.option("headless"), None);
+        assert_eq!(run.option("frames").as_deref(), Some("10"));
+
+        let disasm = args("chip8 disasm --octo rom.ch8");
+        assert_eq!(disasm.positional(1).map(String::as_str), Some("rom.ch8"));
+        assert!(disasm.has_option("octo"));
+
+        let window = args("chip8 --xo-chip --terminal rom.xo8 --debug");
+        assert_eq!(window.positional(0).map(String::as_str), Some("rom.xo8"));
+        assert!(window.has_option("xo-chip") && window.has_option("terminal"));
+    }
+}
@@ -0,0 +1,1085 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::instruction::Instruction;
+use crate::symbols::SymbolTable;
+
+const ORIGIN: u16 = 0x200;
+const MAX_ADDRESS: u32 = 0x10000;
+const MAX_CONSTANT_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(location: &Location, message: impl Into<String>) -> Self {
+        AsmError {
+            location: location.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.location.file, self.location.line, self.location.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub symbols: SymbolTable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Comma,
+    Colon,
+    Equals,
+    Plus,
+    Minus,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(line: &str, location: &Location) -> Result<Vec<Token>, AsmError> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let at = Location {
+            column,
+            ..location.clone()
+        };
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == ';' {
+            break;
+        }
+
+        let kind = match c {
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            '=' => TokenKind::Equals,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '"' => {
+                let mut value = String::new();
+                i += 1;
+
+                loop {
+                    match chars.get(i) {
+                        None => return Err(AsmError::new(&at, "Unterminated string")),
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            value.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            value.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+
+                TokenKind::Str(value)
+            }
+            c if c.is_ascii_digit() || c == '$' || c == '%' => {
+                let start = i;
+                i += 1;
+
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+
+                let text = chars[start..i].iter().collect::<String>();
+                tokens.push(Token {
+                    kind: TokenKind::Number(parse_number(&text, &at)?),
+                    column,
+                });
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+                let start = i;
+
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+
+                tokens.push(Token {
+                    kind: TokenKind::Ident(chars[start..i].iter().collect()),
+                    column,
+                });
+                continue;
+            }
+            c => return Err(AsmError::new(&at, format!("Unexpected character '{}'", c))),
+        };
+
+        tokens.push(Token { kind, column });
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(text: &str, location: &Location) -> Result<i64, AsmError> {
+    let lower = text.to_lowercase().replace('_', "");
+    let result = if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b").or(lower.strip_prefix('%')) {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse::<i64>()
+    };
+
+    result.map_err(|_| AsmError::new(location, format!("Invalid number '{}'", text)))
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Symbol(String, Location),
+    /// Operators keep their own location to report overflows.
+    Neg(Box<Expr>, Location),
+    Add(Box<Expr>, Box<Expr>, Location),
+    Sub(Box<Expr>, Box<Expr>, Location),
+}
+
+#[derive(Debug, Clone)]
+enum OperandKind {
+    Register(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Operand {
+    kind: OperandKind,
+    location: Location,
+}
+
+enum Data {
+    Expr(Expr, Location),
+    Bytes(Vec<u8>),
+}
+
+enum StatementKind {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<Data>),
+    Words(Vec<(Expr, Location)>),
+}
+
+struct Statement {
+    address: u16,
+    location: Location,
+    kind: StatementKind,
+}
+
+impl Statement {
+    fn size(&self) -> u32 {
+        match &self.kind {
+            StatementKind::Instruction { operands, .. } => {
+                if operands
+                    .iter()
+                    .any(|o| matches!(o.kind, OperandKind::Long(_)))
+                {
+                    4
+                } else {
+                    2
+                }
+            }
+            StatementKind::Bytes(data) => data
+                .iter()
+                .map(|d| match d {
+                    Data::Expr(..) => 1,
+                    Data::Bytes(bytes) => bytes.len() as u32,
+                })
+                .sum(),
+            StatementKind::Words(words) => words.len() as u32 * 2,
+        }
+    }
+}
+
+/// Cursor over the tokens of a single line.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    location: &'a Location,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a TokenKind> {
+        self.tokens.get(self.position).map(|t| &t.kind)
+    }
+
+    fn here(&self) -> Location {
+        let column = match self.tokens.get(self.position) {
+            Some(token) => token.column,
+            None => self.tokens.last().map_or(1, |t| t.column + 1),
+        };
+
+        Location {
+            column,
+            ..self.location.clone()
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a TokenKind> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn expect_end(&self) -> Result<(), AsmError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(AsmError::new(&self.here(), "Unexpected token"))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        let mut left = self.factor()?;
+
+        loop {
+            let location = self.here();
+
+            match self.peek() {
+                Some(TokenKind::Plus) => {
+                    self.next();
+                    left = Expr::Add(Box::new(left), Box::new(self.factor()?), location);
+                }
+                Some(TokenKind::Minus) => {
+                    self.next();
+                    left = Expr::Sub(Box::new(left), Box::new(self.factor()?), location);
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, AsmError> {
+        let location = self.here();
+
+        match self.next() {
+            Some(TokenKind::Number(n)) => Ok(Expr::Number(*n)),
+            Some(TokenKind::Ident(name)) => Ok(Expr::Symbol(name.clone(), location)),
+            Some(TokenKind::Minus) => Ok(Expr::Neg(Box::new(self.factor()?), location)),
+            Some(TokenKind::LParen) => {
+                let expr = self.expr()?;
+
+                match self.next() {
+                    Some(TokenKind::RParen) => Ok(expr),
+                    _ => Err(AsmError::new(&location, "Missing closing parenthesis")),
+                }
+            }
+            _ => Err(AsmError::new(&location, "Expected expression")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let location = self.here();
+        let keyword = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(TokenKind::Ident(name)), next)
+                if next.is_none_or(|t| t.kind == TokenKind::Comma) =>
+            {
+                keyword(name)
+            }
+            _ => None,
+        };
+
+        let kind = if let Some(kind) = keyword {
+            self.next();
+            kind
+        } else if self.peek() == Some(&TokenKind::LBracket) {
+            self.next();
+
+            match (self.next(), self.next()) {
+                (Some(TokenKind::Ident(name)), Some(TokenKind::RBracket))
+                    if name.eq_ignore_ascii_case("i") =>
+                {
+                    OperandKind::IndirectI
+                }
+                _ => return Err(AsmError::new(&location, "Expected [I]")),
+            }
+        } else if matches!(self.peek(), Some(TokenKind::Ident(name)) if name.eq_ignore_ascii_case("long"))
+        {
+            self.next();
+            OperandKind::Long(self.expr()?)
+        } else {
+            OperandKind::Expr(self.expr()?)
+        };
+
+        Ok(Operand { kind, location })
+    }
+
+    fn operands(&mut self) -> Result<Vec<Operand>, AsmError> {
+        let mut operands = vec![];
+
+        if self.at_end() {
+            return Ok(operands);
+        }
+
+        loop {
+            operands.push(self.operand()?);
+
+            match self.peek() {
+                Some(TokenKind::Comma) => {
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+
+        self.expect_end()?;
+        Ok(operands)
+    }
+}
+
+fn keyword(name: &str) -> Option<OperandKind> {
+    let upper = name.to_uppercase();
+
+    if let Some(register) = upper.strip_prefix('V') {
+        if register.len() == 1 {
+            return u8::from_str_radix(register, 16)
+                .ok()
+                .map(OperandKind::Register);
+        }
+    }
+
+    match upper.as_str() {
+        "I" => Some(OperandKind::I),
+        "DT" => Some(OperandKind::Dt),
+        "ST" => Some(OperandKind::St),
+        "K" => Some(OperandKind::K),
+        "F" => Some(OperandKind::F),
+        "HF" => Some(OperandKind::Hf),
+        "B" => Some(OperandKind::B),
+        "R" => Some(OperandKind::R),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, (u16, Location)>,
+    constants: HashMap<String, (Expr, Location)>,
+    address: u32,
+    includes: Vec<PathBuf>,
+}
+
+impl Assembler {
+    fn load(&mut self, path: &Path, from: Option<&Location>) -> Result<(), AsmError> {
+        let name = path.display().to_string();
+        let at = from.cloned().unwrap_or(Location {
+            file: name.clone(),
+            line: 0,
+            column: 0,
+        });
+
+        if self.includes.iter().any(|p| p == path) {
+            return Err(AsmError::new(&at, format!("Recursive include of {}", name)));
+        }
+
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| AsmError::new(&at, format!("Could not read {}: {}", name, e)))?;
+
+        self.includes.push(path.to_path_buf());
+        self.parse(&name, &source, path.parent())?;
+        self.includes.pop();
+
+        Ok(())
+    }
+
+    fn parse(&mut self, file: &str, source: &str, dir: Option<&Path>) -> Result<(), AsmError> {
+        for (index, line) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: index + 1,
+                column: 1,
+            };
+            let tokens = tokenize(line, &location)?;
+            let mut parser = Parser {
+                tokens: &tokens,
+                position: 0,
+                location: &location,
+            };
+
+            // Labels: `name:`
+            while let (Some(TokenKind::Ident(name)), Some(TokenKind::Colon)) = (
+                parser.peek(),
+                tokens.get(parser.position + 1).map(|t| &t.kind),
+            ) {
+                let at = parser.here();
+                self.define(name, &at)?;
+
+                if self.address >= MAX_ADDRESS {
+                    return Err(AsmError::new(
+                        &at,
+                        format!("Label '{}' is past the end of memory", name),
+                    ));
+                }
+
+                self.labels
+                    .insert(name.clone(), (self.address as u16, at.clone()));
+                parser.position += 2;
+            }
+
+            let at = parser.here();
+            let name = match parser.next() {
+                None => continue,
+                Some(TokenKind::Ident(name)) => name.clone(),
+                Some(_) => return Err(AsmError::new(&at, "Expected mnemonic or directive")),
+            };
+
+            // Constants: `NAME = expr` or `NAME equ expr`
+            match parser.peek() {
+                Some(TokenKind::Equals) => {
+                    parser.next();
+                    self.constant(&name, &at, &mut parser)?;
+                    continue;
+                }
+                Some(TokenKind::Ident(directive)) if directive.eq_ignore_ascii_case("equ") => {
+                    parser.next();
+                    self.constant(&name, &at, &mut parser)?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let kind = match name.to_lowercase().as_str() {
+                "include" => {
+                    let include = match parser.next() {
+                        Some(TokenKind::Str(include)) => include,
+                        _ => return Err(AsmError::new(&at, "Expected file name after include")),
+                    };
+                    parser.expect_end()?;
+
+                    let path = dir.map_or(PathBuf::from(include), |dir| dir.join(include));
+                    self.load(&path, Some(&at))?;
+                    continue;
+                }
+                "db" => StatementKind::Bytes(self.bytes(&mut parser)?),
+                "dw" => {
+                    let mut words = vec![];
+
+                    loop {
+                        words.push((parser.here(), parser.expr()?));
+
+                        if parser.peek() != Some(&TokenKind::Comma) {
+                            break;
+                        }
+                        parser.next();
+                    }
+                    parser.expect_end()?;
+
+                    StatementKind::Words(words.into_iter().map(|(l, e)| (e, l)).collect())
+                }
+                _ => StatementKind::Instruction {
+                    mnemonic: name.to_uppercase(),
+                    operands: parser.operands()?,
+                },
+            };
+
+            let statement = Statement {
+                address: self.address as u16,
+                location: at,
+                kind,
+            };
+
+            self.address += statement.size();
+
+            if self.address > MAX_ADDRESS {
+                return Err(AsmError::new(
+                    &statement.location,
+                    "Program does not fit in memory",
+                ));
+            }
+
+            self.statements.push(statement);
+        }
+
+        Ok(())
+    }
+
+    fn bytes(&mut self, parser: &mut Parser) -> Result<Vec<Data>, AsmError> {
+        let mut data = vec![];
+
+        loop {
+            let at = parser.here();
+
+            match parser.peek() {
+                Some(TokenKind::Str(text)) => {
+                    parser.next();
+                    data.push(Data::Bytes(text.bytes().collect()));
+                }
+                _ => data.push(Data::Expr(parser.expr()?, at)),
+            }
+
+            if parser.peek() != Some(&TokenKind::Comma) {
+                break;
+            }
+            parser.next();
+        }
+
+        parser.expect_end()?;
+        Ok(data)
+    }
+
+    fn define(&self, name: &str, at: &Location) -> Result<(), AsmError> {
+        if keyword(name).is_some() {
+            return Err(AsmError::new(at, format!("'{}' is a reserved name", name)));
+        }
+
+        let previous = self
+            .labels
+            .get(name)
+            .map(|(_, l)| l)
+            .or(self.constants.get(name).map(|(_, l)| l));
+
+        match previous {
+            Some(previous) => Err(AsmError::new(
+                at,
+                format!(
+                    "'{}' is already defined at {}:{}",
+                    name, previous.line, previous.column
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn constant(&mut self, name: &str, at: &Location, parser: &mut Parser) -> Result<(), AsmError> {
+        self.define(name, at)?;
+        let expr = parser.expr()?;
+        parser.expect_end()?;
+        self.constants.insert(name.to_string(), (expr, at.clone()));
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr, depth: usize) -> Result<i64, AsmError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Neg(e, location) => self
+                .eval(e, depth)?
+                .checked_neg()
+                .ok_or_else(|| AsmError::new(location, "Arithmetic overflow")),
+            Expr::Add(a, b, location) => self
+                .eval(a, depth)?
+                .checked_add(self.eval(b, depth)?)
+                .ok_or_else(|| AsmError::new(location, "Arithmetic overflow")),
+            Expr::Sub(a, b, location) => self
+                .eval(a, depth)?
+                .checked_sub(self.eval(b, depth)?)
+                .ok_or_else(|| AsmError::new(location, "Arithmetic overflow")),
+            Expr::Symbol(name, location) => {
+                if let Some((address, _)) = self.labels.get(name) {
+                    return Ok(*address as i64);
+                }
+
+                match self.constants.get(name) {
+                    Some(_) if depth >= MAX_CONSTANT_DEPTH => Err(AsmError::new(
+                        location,
+                        format!("Constant '{}' refers to itself", name),
+                    )),
+                    Some((expr, _)) => self.eval(expr, depth + 1),
+                    None => Err(AsmError::new(
+                        location,
+                        format!("Undefined symbol '{}'", name),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn value(&self, expr: &Expr, location: &Location, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.eval(expr, 0)?;
+
+        if value < min || value > max {
+            return Err(AsmError::new(
+                location,
+                format!("Value {} out of range {}..={}", value, min, max),
+            ));
+        }
+
+        Ok(value)
+    }
+
+    fn address(&self, operand: &Operand) -> Result<u16, AsmError> {
+        match &operand.kind {
+            OperandKind::Expr(expr) => Ok(self.value(expr, &operand.location, 0, 0xFFF)? as u16),
+            _ => Err(AsmError::new(&operand.location, "Expected address")),
+        }
+    }
+
+    fn byte(&self, operand: &Operand) -> Result<u8, AsmError> {
+        match &operand.kind {
+            OperandKind::Expr(expr) => Ok(self.value(expr, &operand.location, -128, 255)? as u8),
+            _ => Err(AsmError::new(&operand.location, "Expected byte")),
+        }
+    }
+
+    fn nibble(&self, operand: &Operand) -> Result<u8, AsmError> {
+        match &operand.kind {
+            OperandKind::Expr(expr) => Ok(self.value(expr, &operand.location, 0, 15)? as u8),
+            _ => Err(AsmError::new(
+                &operand.location,
+                "Expected value between 0 and 15",
+            )),
+        }
+    }
+
+    fn register(&self, operand: &Operand) -> Result<u8, AsmError> {
+        match operand.kind {
+            OperandKind::Register(x) => Ok(x),
+            _ => Err(AsmError::new(&operand.location, "Expected register V0-VF")),
+        }
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        location: &Location,
+    ) -> Result<Vec<u8>, AsmError> {
+        use OperandKind::*;
+
+        let arity = |count: usize| {
+            if operands.len() == count {
+                Ok(())
+            } else {
+                Err(AsmError::new(
+                    location,
+                    format!(
+                        "{} expects {} operand(s), found {}",
+                        mnemonic,
+                        count,
+                        operands.len()
+                    ),
+                ))
+            }
+        };
+        let invalid = || AsmError::new(location, format!("Invalid operands for {}", mnemonic));
+        let x = |i: usize| self.register(&operands[i]);
+
+        let instruction = match mnemonic {
+            "CLS" => arity(0).map(|_| Instruction::Cls)?,
+            "RET" => arity(0).map(|_| Instruction::Ret)?,
+            "SCR" => arity(0).map(|_| Instruction::Scr)?,
+            "SCL" => arity(0).map(|_| Instruction::Scl)?,
+            "EXIT" => arity(0).map(|_| Instruction::Exit)?,
+            "LOW" => arity(0).map(|_| Instruction::Low)?,
+            "HIGH" => arity(0).map(|_| Instruction::High)?,
+            "AUDIO" => arity(0).map(|_| Instruction::Audio)?,
+            "SCD" => {
+                arity(1)?;
+                Instruction::Scd(self.nibble(&operands[0])?)
+            }
+            "PLANE" => {
+                arity(1)?;
+                Instruction::Plane(self.nibble(&operands[0])?)
+            }
+            "SYS" => {
+                arity(1)?;
+                Instruction::Sys(self.address(&operands[0])?)
+            }
+            "CALL" => {
+                arity(1)?;
+                Instruction::Call(self.address(&operands[0])?)
+            }
+            "JP" => match operands {
+                [Operand {
+                    kind: Register(0), ..
+                }, target] => Instruction::JpV0(self.address(target)?),
+                [target] => Instruction::Jp(self.address(target)?),
+                _ => return Err(invalid()),
+            },
+            "SE" | "SNE" => {
+                arity(2)?;
+                let x = x(0)?;
+
+                match (mnemonic, &operands[1].kind) {
+                    ("SE", Register(y)) => Instruction::SeReg { x, y: *y },
+                    ("SNE", Register(y)) => Instruction::SneReg { x, y: *y },
+                    ("SE", _) => Instruction::SeByte {
+                        x,
+                        byte: self.byte(&operands[1])?,
+                    },
+                    _ => Instruction::SneByte {
+                        x,
+                        byte: self.byte(&operands[1])?,
+                    },
+                }
+            }
+            "SAVE" | "LOAD" => {
+                arity(2)?;
+                let (x, y) = (x(0)?, x(1)?);
+
+                if mnemonic == "SAVE" {
+                    Instruction::SaveRange { x, y }
+                } else {
+                    Instruction::LoadRange { x, y }
+                }
+            }
+            "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" => {
+                // `SHR Vx` and `SHL Vx` are shorthand for shifting a register in place
+                let (x, y) = match operands.len() {
+                    1 if mnemonic.starts_with("SH") => (x(0)?, x(0)?),
+                    _ => {
+                        arity(2)?;
+                        (x(0)?, x(1)?)
+                    }
+                };
+
+                match mnemonic {
+                    "OR" => Instruction::Or { x, y },
+                    "AND" => Instruction::And { x, y },
+                    "XOR" => Instruction::Xor { x, y },
+                    "SUB" => Instruction::Sub { x, y },
+                    "SUBN" => Instruction::Subn { x, y },
+                    "SHR" => Instruction::Shr { x, y },
+                    _ => Instruction::Shl { x, y },
+                }
+            }
+            "ADD" => {
+                arity(2)?;
+
+                match (&operands[0].kind, &operands[1].kind) {
+                    (I, Register(x)) => Instruction::AddIVx(*x),
+                    (Register(x), Register(y)) => Instruction::AddReg { x: *x, y: *y },
+                    (Register(x), Expr(_)) => Instruction::AddByte {
+                        x: *x,
+                        byte: self.byte(&operands[1])?,
+                    },
+                    _ => return Err(invalid()),
+                }
+            }
+            "RND" => {
+                arity(2)?;
+                Instruction::Rnd {
+                    x: x(0)?,
+                    byte: self.byte(&operands[1])?,
+                }
+            }
+            "DRW" => {
+                arity(3)?;
+                Instruction::Drw {
+                    x: x(0)?,
+                    y: x(1)?,
+                    n: self.nibble(&operands[2])?,
+                }
+            }
+            "SKP" => {
+                arity(1)?;
+                Instruction::Skp(x(0)?)
+            }
+            "SKNP" => {
+                arity(1)?;
+                Instruction::Sknp(x(0)?)
+            }
+            "PITCH" => {
+                arity(1)?;
+                Instruction::Pitch(x(0)?)
+            }
+            "LD" => {
+                arity(2)?;
+
+                match (&operands[0].kind, &operands[1].kind) {
+                    (I, Long(expr)) => {
+                        let address = self.value(expr, &operands[1].location, 0, 0xFFFF)?;
+                        return Ok(vec![0xF0, 0x00, (address >> 8) as u8, address as u8]);
+                    }
+                    (I, Expr(_)) => Instruction::LdI(self.address(&operands[1])?),
+                    (Register(x), Register(y)) => Instruction::LdReg { x: *x, y: *y },
+                    (Register(x), Expr(_)) => Instruction::LdByte {
+                        x: *x,
+                        byte: self.byte(&operands[1])?,
+                    },
+                    (Register(x), Dt) => Instruction::LdVxDt(*x),
+                    (Register(x), K) => Instruction::LdVxK(*x),
+                    (Register(x), IndirectI) => Instruction::LdVxI(*x),
+                    (Register(x), R) => Instruction::LdVxR(*x),
+                    (Dt, Register(x)) => Instruction::LdDtVx(*x),
+                    (St, Register(x)) => Instruction::LdStVx(*x),
+                    (F, Register(x)) => Instruction::LdFVx(*x),
+                    (Hf, Register(x)) => Instruction::LdHfVx(*x),
+                    (B, Register(x)) => Instruction::LdBVx(*x),
+                    (IndirectI, Register(x)) => Instruction::LdIVx(*x),
+                    (R, Register(x)) => Instruction::LdRVx(*x),
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => {
+                return Err(AsmError::new(
+                    location,
+                    format!("Unknown mnemonic '{}'", mnemonic),
+                ))
+            }
+        };
+
+        if operands.iter().any(|o| matches!(o.kind, Long(_))) {
+            return Err(invalid());
+        }
+
+        Ok(instruction.encode().to_be_bytes().to_vec())
+    }
+
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = vec![];
+
+        for statement in &self.statements {
+            debug_assert_eq!(rom.len(), (statement.address - ORIGIN) as usize);
+
+            match &statement.kind {
+                StatementKind::Instruction { mnemonic, operands } => {
+                    rom.extend(self.instruction(mnemonic, operands, &statement.location)?);
+                }
+                StatementKind::Bytes(data) => {
+                    for item in data {
+                        match item {
+                            Data::Expr(expr, location) => {
+                                rom.push(self.value(expr, location, -128, 255)? as u8)
+                            }
+                            Data::Bytes(bytes) => rom.extend(bytes),
+                        }
+                    }
+                }
+                StatementKind::Words(words) => {
+                    for (expr, location) in words {
+                        let word = self.value(expr, location, -0x8000, 0xFFFF)? as u16;
+                        rom.extend(word.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        Ok(rom)
+    }
+}
+
+/// Assembles the source file at `path`, resolving includes relative to the
+/// file that contains them.
+pub fn assemble(path: &Path) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler {
+        address: ORIGIN as u32,
+        ..Default::default()
+    };
+
+    assembler.load(path, None)?;
+
+    let rom = assembler.emit()?;
+    let mut symbols = SymbolTable::default();
+
+    for (name, (address, _)) in &assembler.labels {
+        symbols.insert(*address, name);
+    }
+
+    Ok(Assembly { rom, symbols })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_source(source: &str) -> Result<Vec<u8>, AsmError> {
+        let mut assembler = Assembler {
+            address: ORIGIN as u32,
+            ..Default::default()
+        };
+
+        assembler.parse("test.asm", source, None)?;
+        assembler.emit()
+    }
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let error = assemble_source(source).unwrap_err();
+        (error.location.line, error.location.column, error.message)
+    }
+
+    #[test]
+    fn reports_arithmetic_overflow() {
+        assert_eq!(
+            error_at("DB 1\nDB 0x7FFFFFFFFFFFFFFF + 1"),
+            (2, 23, "Arithmetic overflow".to_string())
+        );
+        assert_eq!(
+            error_at("X = 0 - 0x7FFFFFFFFFFFFFFF\nDW X - 2"),
+            (2, 6, "Arithmetic overflow".to_string())
+        );
+        assert_eq!(
+            error_at("X = -0x7FFFFFFFFFFFFFFF - 1\nDW -X"),
+            (2, 4, "Arithmetic overflow".to_string())
+        );
+    }
+
+    #[test]
+    fn resolves_labels_and_forward_references() {
+        let rom = assemble_source(
+            "start:  CALL draw\n\
+             \x20       JP start\n\
+             draw:   LD I, sprite\n\
+             \x20       RET\n\
+             sprite: DB 0x80",
+        )
+        .unwrap();
+
+        assert_eq!(rom, [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0x80]);
+    }
+
+    #[test]
+    fn evaluates_constants() {
+        let rom = assemble_source(
+            "SPEED = 3\n\
+             LIMIT equ SPEED + (10 - 2)\n\
+             LD V1, LIMIT\n\
+             ADD V1, -SPEED",
+        )
+        .unwrap();
+
+        assert_eq!(rom, [0x61, 0x0B, 0x71, 0xFD]);
+        assert_eq!(
+            error_at("X = Y\nY = X\nLD V0, X").2,
+            "Constant 'X' refers to itself"
+        );
+    }
+
+    #[test]
+    fn emits_data() {
+        let rom = assemble_source("DB 1, -1, \"hi\"\nDW 0xBEEF, end\nend:").unwrap();
+
+        assert_eq!(rom, [0x01, 0xFF, b'h', b'i', 0xBE, 0xEF, 0x02, 0x08]);
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_source() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("main.asm"),
+            "include \"lib/sprite.asm\"\nLD I, sprite",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/sprite.asm"), "sprite: DB 0xF0").unwrap();
+        std::fs::write(dir.join("loop.asm"), "include \"loop.asm\"").unwrap();
+
+        let assembly = assemble(&dir.join("main.asm")).unwrap();
+        assert_eq!(assembly.rom, [0xF0, 0xA2, 0x00]);
+        assert_eq!(assembly.symbols.to_string(), "0x0200 sprite\n");
+
+        let error = assemble(&dir.join("loop.asm")).err().unwrap();
+        assert!(error.message.starts_with("Recursive include"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_errors_with_line_and_column() {
+        assert_eq!(
+            error_at("CLS\n  LD V0, 256"),
+            (2, 10, "Value 256 out of range -128..=255".to_string())
+        );
+        assert_eq!(
+            error_at("JP nowhere"),
+            (1, 4, "Undefined symbol 'nowhere'".to_string())
+        );
+        assert_eq!(
+            error_at("a: CLS\na: CLS"),
+            (2, 1, "'a' is already defined at 1:1".to_string())
+        );
+        assert_eq!(
+            error_at("CLS V0"),
+            (1, 1, "CLS expects 0 operand(s), found 1".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_labels_past_the_end_of_memory() {
+        let full = format!(
+            "DB \"{}\"",
+            "x".repeat(MAX_ADDRESS as usize - ORIGIN as usize)
+        );
+
+        assert_eq!(assemble_source(&full).unwrap().len(), 0xFE00);
+        assert_eq!(
+            error_at(&format!("{}\nend:", full)),
+            (2, 1, "Label 'end' is past the end of memory".to_string())
+        );
+    }
+
+    #[test]
+    fn reassembles_disassembled_roms() {
+        for entry in std::fs::read_dir("roms").unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_none_or(|extension| extension != "ch8") {
+                continue;
+            }
+
+            let rom = std::fs::read(&path).unwrap();
+            let source = crate::disasm::Disassembly::new(&rom).source();
+
+            match assemble_source(&source) {
+                Ok(reassembled) => assert!(reassembled == rom, "{} differs", path.display()),
+                Err(e) => panic!("{}: {}", path.display(), e),
+            }
+        }
+    }
+}
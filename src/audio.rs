@@ -0,0 +1,67 @@
+use sdl2::audio::AudioCallback;
+
+const PATTERN_BITS: f32 = 128.0;
+
+pub struct SquareWave {
+    pub phase_inc: f32,
+    pub phase: f32,
+    pub volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // Generate a square wave
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// Plays the 128-bit XO-CHIP audio pattern buffer at the rate selected by
+/// the pitch register.
+pub struct PatternPlayer {
+    pub pattern: [u8; 16],
+    pub rate: f32,
+    pub sample_rate: f32,
+    pub position: f32,
+    pub volume: f32,
+}
+
+impl AudioCallback for PatternPlayer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let step = self.rate / self.sample_rate;
+
+        for x in out.iter_mut() {
+            let bit = self.position as usize;
+            let on = self.pattern[bit / 8] >> (7 - bit % 8) & 1 != 0;
+
+            *x = if on { self.volume } else { -self.volume };
+            self.position = (self.position + step) % PATTERN_BITS;
+        }
+    }
+}
+
+pub enum Beeper {
+    Square(SquareWave),
+    Pattern(PatternPlayer),
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        match self {
+            Beeper::Square(wave) => wave.callback(out),
+            Beeper::Pattern(player) => player.callback(out),
+        }
+    }
+}
@@ -1,7 +1,41 @@
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
-const DISPLAY_SIZE: usize = (DISPLAY_WIDTH / 8) * DISPLAY_HEIGHT;
-
+use crate::config::Config;
+use crate::coverage::{self, Coverage};
+use crate::error::{Chip8Error, StateError};
+use crate::font::{
+    BigFontSet, FontSet, BIG_FONT_ADDRESS, BIG_FONT_GLYPH_SIZE, FONT_ADDRESS, FONT_GLYPH_SIZE,
+};
+use crate::instruction::{decode, Instruction};
+use crate::movie::{Movie, HASH_INTERVAL};
+use crate::profiler::Profiler;
+use crate::quirks::{LoadStoreIncrement, Quirks};
+use crate::rewind::RewindBuffer;
+use crate::rng::{RandomMode, RandomSource};
+use crate::state::{self, fnv1a, StateReader, StateWriter};
+use crate::trace::Tracer;
+use crate::watch::{Access, WatchHit, Watchpoint};
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+const DISPLAY_SIZE: usize = (HIRES_WIDTH / 8) * HIRES_HEIGHT;
+pub const PLANES: usize = 2;
+
+const MEMORY_SIZE: usize = 0x1000;
+const XO_MEMORY_SIZE: usize = 0x10000;
+const PROGRAM_START: usize = 0x200;
+const STACK_SIZE: usize = 16;
+const RPL_SIZE: usize = 16;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_AUDIO_PATTERN: [u8; AUDIO_PATTERN_SIZE] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+const DEFAULT_PITCH: u8 = 64;
+
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyMask {
     Key0 = 1,
     Key1 = 1 << 1,
@@ -21,67 +55,601 @@ pub enum KeyMask {
     KeyF = 1 << 15,
 }
 
+/// Copy of the CPU registers, for frontends and tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub stack: [u16; STACK_SIZE],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+#[derive(Clone)]
 pub struct Chip8 {
-    memory: [u8; 4096],
+    memory: [u8; XO_MEMORY_SIZE],
     v: [u8; 16],
     i: u16,
     pc: u16,
-    stack: [u16; 16],
+    stack: [u16; STACK_SIZE],
     sp: u8,
     delay_timer: u8,
-    pub sound_timer: u8,
-    pub keypad: u16,
-    pub old_keypad: u16,
-    pub display: [u8; DISPLAY_SIZE],
-    pub halted: bool,
+    sound_timer: u8,
+    keypad: u16,
+    old_keypad: u16,
+    display: [[u8; DISPLAY_SIZE]; PLANES],
+    hires: bool,
+    planes: u8,
+    halted: bool,
+    rpl: [u8; RPL_SIZE],
+    font: FontSet,
+    big_font: BigFontSet,
+    cycles_per_frame: u32,
+    quirks: Quirks,
+    vblank_wait: bool,
+    xo_chip: bool,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    rom_hash: u64,
+    rewind: Option<RewindBuffer>,
+    rng: Box<dyn RandomSource>,
+    frame: u64,
+    cycle: u32,
+    movie: Option<Movie>,
+    watchpoints: Vec<Watchpoint>,
+    watch_access: Option<(Access, u16)>,
+    watch_hit: Option<WatchHit>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
-        Chip8 {
-            memory: [0; 4096],
+        let mut chip8 = Chip8 {
+            memory: [0; XO_MEMORY_SIZE],
             v: [0; 16],
             i: 0,
-            pc: 0x200,
-            stack: [0; 16],
+            pc: PROGRAM_START as u16,
+            stack: [0; STACK_SIZE],
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
             old_keypad: 0,
             keypad: 0,
-            display: [0; DISPLAY_SIZE],
+            display: [[0; DISPLAY_SIZE]; PLANES],
+            hires: false,
+            planes: 1,
             halted: false,
-        }
+            rpl: [0; RPL_SIZE],
+            font: FontSet::default(),
+            big_font: BigFontSet::default(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            quirks: Quirks::default(),
+            vblank_wait: false,
+            xo_chip: false,
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
+            rom_hash: fnv1a(&[]),
+            rewind: None,
+            rng: RandomMode::default().source(rand::random()),
+            frame: 0,
+            cycle: 0,
+            movie: None,
+            watchpoints: vec![],
+            watch_access: None,
+            watch_hit: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+        };
+        chip8.load_fonts();
+        chip8
+    }
+
+    pub fn with_config(config: &Config) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_font(config.font);
+        chip8.set_big_font(config.big_font);
+        chip8.set_cycles_per_frame(config.cycles_per_frame);
+        chip8.set_quirks(config.quirks);
+        chip8.set_xo_chip(config.xo_chip);
+        chip8.set_random_source(
+            config
+                .random
+                .source(config.seed.unwrap_or_else(rand::random)),
+        );
+        chip8.set_rewind_capacity(config.rewind);
+        chip8
     }
 
     pub fn reset(&mut self) {
-        println!("Resetando...");
-        self.memory = [0; 4096];
+        self.memory = [0; XO_MEMORY_SIZE];
         self.v = [0; 16];
         self.i = 0;
-        self.pc = 0x200;
-        self.stack = [0; 16];
+        self.pc = PROGRAM_START as u16;
+        self.stack = [0; STACK_SIZE];
         self.sp = 0;
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.keypad = 0;
-        self.display = [0; DISPLAY_SIZE];
+        self.display = [[0; DISPLAY_SIZE]; PLANES];
+        self.hires = false;
+        self.planes = 1;
         self.halted = false;
+        self.vblank_wait = false;
+        self.audio_pattern = DEFAULT_AUDIO_PATTERN;
+        self.pitch = DEFAULT_PITCH;
+        self.frame = 0;
+        self.cycle = 0;
+        self.load_fonts();
+    }
+
+    pub fn set_font(&mut self, font: FontSet) {
+        self.font = font;
+        self.load_fonts();
+    }
+
+    pub fn set_big_font(&mut self, big_font: BigFontSet) {
+        self.big_font = big_font;
+        self.load_fonts();
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles_per_frame: u32) {
+        self.cycles_per_frame = cycles_per_frame.max(1);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn set_xo_chip(&mut self, xo_chip: bool) {
+        self.xo_chip = xo_chip;
+
+        // Snapshots only hold the active memory, so older ones no longer fit
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+            self.record_frame();
+        }
+    }
+
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    /// Keeps the last `frames` frames for `rewind`; 0 turns it off.
+    pub fn set_rewind_capacity(&mut self, frames: usize) {
+        self.rewind = match frames {
+            0 => None,
+            frames => Some(RewindBuffer::new(frames)),
+        };
+        self.record_frame();
+    }
+
+    fn memory_size(&self) -> usize {
+        if self.xo_chip {
+            XO_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        }
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            stack: self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Frames left before the speaker turns off.
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Keys held down, one bit per key as in [`KeyMask`].
+    pub fn keypad(&self) -> u16 {
+        self.keypad
+    }
+
+    /// Whether the machine was stopped, by `EXIT` or [`Chip8::halt`].
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Overwrites the CPU registers, for debuggers.
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.v = registers.v;
+        self.i = registers.i;
+        self.pc = registers.pc;
+        self.sp = registers.sp.min(STACK_SIZE as u8);
+        self.stack = registers.stack;
+        self.delay_timer = registers.delay_timer;
+        self.sound_timer = registers.sound_timer;
+    }
+
+    /// The addressable memory: 4 KiB, or 64 KiB in XO-CHIP mode.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..self.memory_size()]
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        let size = self.memory_size();
+        &mut self.memory[..size]
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes the watchpoints for which `matches` is true and returns how
+    /// many there were.
+    pub fn remove_watchpoints(&mut self, matches: impl Fn(&Watchpoint) -> bool) -> usize {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| !matches(w));
+        count - self.watchpoints.len()
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The watched access that stopped the last `run_frame`, if any.
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    /// Number of frames run since the ROM was loaded.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Hash of the whole machine state, used to detect movie desyncs.
+    pub fn state_hash(&self) -> u64 {
+        fnv1a(&self.snapshot())
+    }
+
+    /// Starts logging every keypad change, along with periodic state
+    /// hashes, into `movie`.
+    pub fn start_recording(&mut self, movie: Movie) {
+        self.movie = Some(movie);
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        self.movie.take()
+    }
+
+    pub fn start_tracing(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn stop_tracing(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn start_profiling(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn start_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn audio_pattern(&self) -> [u8; AUDIO_PATTERN_SIZE] {
+        self.audio_pattern
+    }
+
+    /// Rate, in bits per second, at which the XO-CHIP audio pattern is played.
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    fn load_fonts(&mut self) {
+        let font = self.font.glyphs();
+        self.memory[FONT_ADDRESS..FONT_ADDRESS + font.len()].copy_from_slice(font);
+
+        let big_font = self.big_font.glyphs();
+        self.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + big_font.len()].copy_from_slice(big_font);
+    }
+
+    pub fn display_width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+
+    pub fn display_height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    /// Colour index of a pixel: bit 0 comes from the first plane and bit 1
+    /// from the second one, so plain CHIP-8 programs only produce 0 and 1.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        (0..PLANES).fold(0, |color, plane| {
+            color | (self.plane_pixel(plane, x, y) as u8) << plane
+        })
+    }
+
+    /// Colour indices of the whole display, row by row.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let width = self.display_width();
+
+        (0..self.display_height())
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.pixel(x, y))
+            .collect()
+    }
+
+    fn plane_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        let index = y * self.display_width() / 8 + x / 8;
+        self.display[plane][index] & (0x80 >> (x % 8)) != 0
+    }
+
+    fn set_plane_pixel(&mut self, plane: usize, x: usize, y: usize, on: bool) {
+        let index = y * self.display_width() / 8 + x / 8;
+        let mask = 0x80 >> (x % 8);
+
+        if on {
+            self.display[plane][index] |= mask;
+        } else {
+            self.display[plane][index] &= !mask;
+        }
+    }
+
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..PLANES).filter(move |plane| planes & (1 << plane) != 0)
     }
 
     pub fn halt(&mut self) {
         self.halted = true;
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) {
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory_size() - PROGRAM_START;
+
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+
         self.reset();
-        println!("Carregando ROM...");
-        for (i, &byte) in rom.iter().enumerate() {
-            self.memory[i + 0x200] = byte;
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.rom_hash = fnv1a(rom);
+
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
         }
+        self.record_frame();
+
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        state::encode(self.rom_hash, &self.snapshot())
+    }
+
+    /// Restores a state produced by `save_state` for the same ROM. The
+    /// machine is left untouched when the state is rejected.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.restore(state::decode(state, self.rom_hash)?)
     }
 
-    pub fn tick(&mut self) {
+    fn snapshot(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+
+        state.bytes(&self.memory[..self.memory_size()]);
+        state.bytes(&self.v);
+        state.u16(self.i);
+        state.u16(self.pc);
+        self.stack.iter().for_each(|&address| state.u16(address));
+        state.u8(self.sp);
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u16(self.keypad);
+        state.u16(self.old_keypad);
+        self.display.iter().for_each(|plane| state.bytes(plane));
+        state.bool(self.hires);
+        state.u8(self.planes);
+        state.bool(self.halted);
+        state.bytes(&self.rpl);
+        state.bool(self.vblank_wait);
+        state.bytes(&self.audio_pattern);
+        state.u8(self.pitch);
+        state.u64(self.rng.state());
+        state.u64(self.frame);
+
+        state.finish()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), StateError> {
+        // Keeps the rewind history and the debugging tools out of the copy
+        // made while reading
+        let rewind = self.rewind.take();
+        let movie = self.movie.take();
+        let tracer = self.tracer.take();
+        let profiler = self.profiler.take();
+        let coverage = self.coverage.take();
+        let result = self.read_snapshot(snapshot);
+        self.rewind = rewind;
+        self.movie = movie;
+        self.tracer = tracer;
+        self.profiler = profiler;
+        self.coverage = coverage;
+
+        if let Some(movie) = &mut self.movie {
+            movie.truncate(self.frame);
+        }
+
+        result
+    }
+
+    fn read_snapshot(&mut self, snapshot: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(snapshot);
+        let mut next = self.clone();
+
+        let memory_size = next.memory_size();
+        state.fill(&mut next.memory[..memory_size])?;
+        state.fill(&mut next.v)?;
+        next.i = state.u16()?;
+        next.pc = state.u16()?;
+        for address in next.stack.iter_mut() {
+            *address = state.u16()?;
+        }
+        next.sp = state.u8()?;
+        next.delay_timer = state.u8()?;
+        next.sound_timer = state.u8()?;
+        next.keypad = state.u16()?;
+        next.old_keypad = state.u16()?;
+        for plane in next.display.iter_mut() {
+            state.fill(plane)?;
+        }
+        next.hires = state.bool()?;
+        next.planes = state.u8()?;
+        next.halted = state.bool()?;
+        state.fill(&mut next.rpl)?;
+        next.vblank_wait = state.bool()?;
+        state.fill(&mut next.audio_pattern)?;
+        next.pitch = state.u8()?;
+        next.rng.set_state(state.u64()?);
+        next.frame = state.u64()?;
+        // Snapshots are taken between frames
+        next.cycle = 0;
+        state.finish()?;
+
+        if next.sp as usize > STACK_SIZE {
+            return Err(StateError::Corrupted);
+        }
+
+        *self = next;
+        Ok(())
+    }
+
+    fn record_frame(&mut self) {
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.push(self.snapshot());
+            self.rewind = Some(rewind);
+        }
+    }
+
+    /// Goes back up to `frames` frames and returns how many were undone.
+    /// Running forward again from there replays the same frames as long as
+    /// the input is the same.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let mut rewind = match self.rewind.take() {
+            Some(rewind) => rewind,
+            None => return 0,
+        };
+
+        let steps = match rewind.rewind(frames) {
+            Some((steps, snapshot)) => {
+                self.restore(snapshot)
+                    .expect("rewind snapshots are always complete");
+                steps
+            }
+            None => 0,
+        };
+
+        self.rewind = Some(rewind);
+        steps
+    }
+
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.run_frame(|_| false).map(|_| ())
+    }
+
+    /// Runs the rest of the current frame, stopping before any instruction
+    /// for which `stop` returns true or after one that hits a watchpoint.
+    /// Returns whether the frame was finished; a stopped frame picks up where
+    /// it left off on the next call.
+    pub fn run_frame(&mut self, mut stop: impl FnMut(&Chip8) -> bool) -> Result<bool, Chip8Error> {
+        self.watch_hit = None;
+
+        if self.cycle == 0 {
+            self.vblank_wait = false;
+        }
+
+        while self.cycle < self.cycles_per_frame && !self.halted && !self.vblank_wait {
+            if stop(self) {
+                return Ok(false);
+            }
+
+            self.cycle += 1;
+            self.emulate_cycle()?;
+
+            if self.watch_hit.is_some() {
+                return Ok(false);
+            }
+        }
+
+        self.cycle = 0;
+        self.update_timers();
+        self.frame += 1;
+        self.record_frame();
+
+        if self.frame.is_multiple_of(HASH_INTERVAL) {
+            if let Some(mut movie) = self.movie.take() {
+                movie.record_hash(self.frame, self.state_hash());
+                self.movie = Some(movie);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Runs exactly one instruction, finishing the current frame first when
+    /// it has no cycles left.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let mut started = false;
+
+        while !started && !self.halted {
+            self.run_frame(|_| std::mem::replace(&mut started, true))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn update_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -89,307 +657,483 @@ impl Chip8 {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+    }
 
-        self.emulate_cycle();
-    }
-
-    pub fn emulate_cycle(&mut self) {
-        let opcode =
-            (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
-
-        match opcode {
-            0x00E0 => self.op_clr(),
-            0x00EE => self.op_rts(),
-            0x1000..=0x1FFF => self.op_jmp(opcode),
-            0x2000..=0x2FFF => self.op_call(opcode),
-            0x00C0..=0x00CF => self.op_scrd(opcode),
-            0x0000..=0x0FFF => self.op_sys(),
-            0x3000..=0x3FFF => self.op_ske(opcode),
-            0x4000..=0x4FFF => self.op_skne(opcode),
-            0x5000..=0x5FFF => self.op_skre(opcode),
-            0x6000..=0x6FFF => self.op_load(opcode),
-            0x7000..=0x7FFF => self.op_add(opcode),
-            0x8000..=0x8FFF => match opcode & 0x000F {
-                0x0000 => self.op_move(opcode),
-                0x0001 => self.op_or(opcode),
-                0x0002 => self.op_and(opcode),
-                0x0003 => self.op_xor(opcode),
-                0x0004 => self.op_addr(opcode),
-                0x0005 => self.op_sub(opcode),
-                0x0006 => self.op_shr(opcode),
-                0x0007 => self.op_subn(opcode),
-                0x000E => self.op_shl(opcode),
-                _ => println!("Unknown opcode: {:#X}", opcode),
-            },
-            0x9000..=0x9FFF => self.op_skrne(opcode),
-            0xA000..=0xAFFF => self.op_loadi(opcode),
-            0xB000..=0xBFFF => self.op_jumpi(opcode),
-            0xC000..=0xCFFF => self.op_rand(opcode),
-            0xD000..=0xDFFF => self.op_draw(opcode),
-            0xE000..=0xEFFF => match opcode & 0x00FF {
-                0x009E => self.op_spr(opcode),
-                0x00A1 => self.op_skup(opcode),
-                _ => println!("Unknown opcode: {:#X}", opcode),
-            },
-            0xF000..=0xFFFF => match opcode & 0x00FF {
-                0x07 => self.op_moved(opcode),
-                0x0A => self.op_keyd(opcode),
-                0x15 => self.op_loadd(opcode),
-                0x18 => self.op_loads(opcode),
-                0x1E => self.op_addi(opcode),
-                0x29 => self.op_ldspr(opcode),
-                0x33 => self.op_bcd(opcode),
-                0x55 => self.op_stor(opcode),
-                0x65 => self.op_read(opcode),
-                _ => println!("Unknown opcode: {:#X}", opcode),
-            },
+    pub fn emulate_cycle(&mut self) -> Result<(), Chip8Error> {
+        let result = self.fetch_and_execute();
+
+        if let (Err(e), Some(tracer)) = (&result, &mut self.tracer) {
+            tracer.error(e);
+        }
+
+        result
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<(), Chip8Error> {
+        let address = self.pc as usize;
+
+        if address + 1 >= self.memory_size() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                address: self.pc,
+                opcode: 0,
+                target: address + 1,
+            });
+        }
+
+        self.watch_access = None;
+        let opcode = (self.read(address, Access::Execute) as u16) << 8
+            | self.read(address + 1, Access::Execute) as u16;
+
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.record(self, opcode);
+            self.tracer = Some(tracer);
         }
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(self.pc, opcode, self.sp);
+        }
+
+        self.execute(decode(opcode))?;
         self.old_keypad = self.keypad;
+
+        if let Some((access, target)) = self.watch_access.take() {
+            self.watch_hit = Some(WatchHit {
+                access,
+                address: target,
+                pc: address as u16,
+                opcode,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        let opcode = instruction.encode();
+
+        if instruction.is_xo_chip() && !self.xo_chip {
+            return Err(self.unknown_opcode(opcode));
+        }
+
+        match instruction {
+            Instruction::Sys(_) => self.op_sys(),
+            Instruction::Cls => self.op_clr(),
+            Instruction::Ret => self.op_rts(opcode)?,
+            Instruction::Scd(n) => self.op_scrd(n as usize),
+            Instruction::Scr => self.op_scrr(),
+            Instruction::Scl => self.op_scrl(),
+            Instruction::Exit => self.op_exit(),
+            Instruction::Low => self.op_low(),
+            Instruction::High => self.op_high(),
+            Instruction::Jp(addr) => self.op_jmp(addr),
+            Instruction::Call(addr) => self.op_call(addr, opcode)?,
+            Instruction::SeByte { x, byte } => self.op_ske(x as usize, byte),
+            Instruction::SneByte { x, byte } => self.op_skne(x as usize, byte),
+            Instruction::SeReg { x, y } => self.op_skre(x as usize, y as usize),
+            Instruction::SaveRange { x, y } => self.op_save(x as usize, y as usize, opcode)?,
+            Instruction::LoadRange { x, y } => self.op_restore(x as usize, y as usize, opcode)?,
+            Instruction::LdByte { x, byte } => self.op_load(x as usize, byte),
+            Instruction::AddByte { x, byte } => self.op_add(x as usize, byte),
+            Instruction::LdReg { x, y } => self.op_move(x as usize, y as usize),
+            Instruction::Or { x, y } => self.op_or(x as usize, y as usize),
+            Instruction::And { x, y } => self.op_and(x as usize, y as usize),
+            Instruction::Xor { x, y } => self.op_xor(x as usize, y as usize),
+            Instruction::AddReg { x, y } => self.op_addr(x as usize, y as usize),
+            Instruction::Sub { x, y } => self.op_sub(x as usize, y as usize),
+            Instruction::Shr { x, y } => self.op_shr(x as usize, y as usize),
+            Instruction::Subn { x, y } => self.op_subn(x as usize, y as usize),
+            Instruction::Shl { x, y } => self.op_shl(x as usize, y as usize),
+            Instruction::SneReg { x, y } => self.op_skrne(x as usize, y as usize),
+            Instruction::LdI(addr) => self.op_loadi(addr),
+            Instruction::JpV0(addr) => self.op_jumpi(addr),
+            Instruction::Rnd { x, byte } => self.op_rand(x as usize, byte),
+            Instruction::Drw { x, y, n } => {
+                self.op_draw(x as usize, y as usize, n as usize, opcode)?
+            }
+            Instruction::Skp(x) => self.op_spr(x as usize),
+            Instruction::Sknp(x) => self.op_skup(x as usize),
+            Instruction::LdILong => self.op_loadil(opcode)?,
+            Instruction::Plane(n) => self.op_plane(n),
+            Instruction::Audio => self.op_audio(opcode)?,
+            Instruction::LdVxDt(x) => self.op_moved(x as usize),
+            Instruction::LdVxK(x) => self.op_keyd(x as usize),
+            Instruction::LdDtVx(x) => self.op_loadd(x as usize),
+            Instruction::LdStVx(x) => self.op_loads(x as usize),
+            Instruction::AddIVx(x) => self.op_addi(x as usize),
+            Instruction::LdFVx(x) => self.op_ldspr(x as usize),
+            Instruction::LdHfVx(x) => self.op_ldhspr(x as usize),
+            Instruction::LdBVx(x) => self.op_bcd(x as usize, opcode)?,
+            Instruction::Pitch(x) => self.op_pitch(x as usize),
+            Instruction::LdIVx(x) => self.op_stor(x as usize, opcode)?,
+            Instruction::LdVxI(x) => self.op_read(x as usize, opcode)?,
+            Instruction::LdRVx(x) => self.op_srpl(x as usize),
+            Instruction::LdVxR(x) => self.op_lrpl(x as usize),
+            Instruction::Unknown(opcode) => return Err(self.unknown_opcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    // Every memory access made by the program goes through `read`,
+    // `read_sprite` and `write` so watchpoints and coverage can see it
+    fn read(&mut self, address: usize, access: Access) -> u8 {
+        self.watch(address, access);
+        self.cover(
+            address,
+            match access {
+                Access::Execute => coverage::EXECUTED,
+                _ => coverage::READ,
+            },
+        );
+        self.memory[address]
+    }
+
+    fn read_sprite(&mut self, address: usize) -> u8 {
+        self.watch(address, Access::Read);
+        self.cover(address, coverage::SPRITE);
+        self.memory[address]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.watch(address, Access::Write);
+        self.cover(address, coverage::WRITTEN);
+        self.memory[address] = value;
+    }
+
+    fn cover(&mut self, address: usize, flags: u8) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(address, flags);
+        }
+    }
+
+    fn watch(&mut self, address: usize, access: Access) {
+        if self.watch_access.is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|w| w.matches(address as u16, access))
+        {
+            self.watch_access = Some((access, address as u16));
+        }
+    }
+
+    fn unknown_opcode(&self, opcode: u16) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            address: self.pc,
+            opcode,
+        }
+    }
+
+    fn check_memory(&self, opcode: u16, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if start + len > self.memory_size() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                address: self.pc,
+                opcode,
+                target: start + len - 1,
+            });
+        }
+
+        Ok(())
+    }
+
+    // XO-CHIP programs wrap from 0xFFFE to 0x0000, the other modes stop
+    // with MemoryOutOfBounds on the next fetch
+    fn advance(&mut self, bytes: u16) {
+        self.pc = self.pc.wrapping_add(bytes);
+    }
+
+    fn skip_next(&mut self) {
+        let address = self.pc as usize;
+        let long = self.xo_chip && self.memory.get(address..address + 2) == Some(&[0xF0, 0x00]);
+
+        self.advance(if long { 4 } else { 2 });
     }
 
     fn op_sys(&mut self) {
-        self.pc += 2;
+        self.advance(2);
     }
 
     fn op_clr(&mut self) {
-        self.pc += 2;
-        self.display = [0; DISPLAY_SIZE];
+        self.advance(2);
+
+        for plane in self.selected_planes() {
+            self.display[plane] = [0; DISPLAY_SIZE];
+        }
     }
 
-    fn op_rts(&mut self) {
-        self.pc += 2;
-        self.pc = self.stack[self.sp as usize];
+    fn op_rts(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                address: self.pc,
+                opcode,
+            });
+        }
+
         self.sp -= 1;
+        self.pc = self.stack[self.sp as usize];
+        Ok(())
     }
 
-    fn op_jmp(&mut self, opcode: u16) {
-        self.pc += 2;
-        self.pc = opcode & 0x0FFF;
+    fn op_jmp(&mut self, addr: u16) {
+        self.advance(2);
+        self.pc = addr;
     }
 
-    fn op_call(&mut self, opcode: u16) {
-        self.pc += 2;
-        self.sp += 1;
+    fn op_call(&mut self, addr: u16, opcode: u16) -> Result<(), Chip8Error> {
+        if self.sp as usize >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow {
+                address: self.pc,
+                opcode,
+            });
+        }
+
+        self.advance(2);
         self.stack[self.sp as usize] = self.pc;
-        self.pc = opcode & 0x0FFF;
+        self.sp += 1;
+        self.pc = addr;
+        Ok(())
     }
 
-    fn op_ske(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn op_ske(&mut self, x: usize, nn: u8) {
+        self.advance(2);
 
         if self.v[x] == nn {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_skne(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn op_skne(&mut self, x: usize, nn: u8) {
+        self.advance(2);
 
         if self.v[x] != nn {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_skre(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_skre(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         if self.v[x] == self.v[y] {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_load(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn op_load(&mut self, x: usize, nn: u8) {
+        self.advance(2);
 
         self.v[x] = nn;
     }
 
-    fn op_add(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn op_add(&mut self, x: usize, nn: u8) {
+        self.advance(2);
 
         self.v[x] = self.v[x].wrapping_add(nn);
     }
 
-    fn op_move(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_move(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         self.v[x] = self.v[y];
     }
 
-    fn op_or(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_or(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         self.v[x] |= self.v[y];
+
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
 
-    fn op_and(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_and(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         self.v[x] &= self.v[y];
+
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
 
-    fn op_xor(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_xor(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         self.v[x] ^= self.v[y];
+
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
 
-    fn op_addr(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_addr(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         let (result, overflow) = self.v[x].overflowing_add(self.v[y]);
         self.v[x] = result;
         self.v[0xF] = overflow as u8;
     }
 
-    fn op_sub(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_sub(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         let (result, overflow) = self.v[x].overflowing_sub(self.v[y]);
         self.v[x] = result;
         self.v[0xF] = !overflow as u8;
     }
 
-    fn op_subn(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_subn(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         let (result, overflow) = self.v[y].overflowing_sub(self.v[x]);
         self.v[x] = result;
         self.v[0xF] = !overflow as u8;
     }
 
-    fn op_shr(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_shr(&mut self, x: usize, y: usize) {
+        self.advance(2);
+        let value = if self.quirks.shift_uses_vy {
+            self.v[y]
+        } else {
+            self.v[x]
+        };
 
-        self.v[0xF] = self.v[x] & 0x1;
-        self.v[x] >>= 1;
+        self.v[x] = value >> 1;
+        self.v[0xF] = value & 0x1;
     }
 
-    fn op_shl(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_shl(&mut self, x: usize, y: usize) {
+        self.advance(2);
+        let value = if self.quirks.shift_uses_vy {
+            self.v[y]
+        } else {
+            self.v[x]
+        };
 
-        self.v[0xF] = self.v[x] >> 7;
-        self.v[x] <<= 1;
+        self.v[x] = value << 1;
+        self.v[0xF] = value >> 7;
     }
 
-    fn op_skrne(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn op_skrne(&mut self, x: usize, y: usize) {
+        self.advance(2);
 
         if self.v[x] != self.v[y] {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_loadi(&mut self, opcode: u16) {
-        self.pc += 2;
-        self.i = opcode & 0x0FFF;
+    fn op_loadi(&mut self, addr: u16) {
+        self.advance(2);
+        self.i = addr;
     }
 
-    fn op_jumpi(&mut self, opcode: u16) {
-        self.pc += 2;
-        self.pc = self.v[0] as u16 + (opcode & 0x0FFF);
+    fn op_jumpi(&mut self, addr: u16) {
+        let x = if self.quirks.jump_uses_vx {
+            (addr >> 8) as usize
+        } else {
+            0
+        };
+
+        self.pc = self.v[x] as u16 + addr;
     }
 
-    fn op_rand(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn op_rand(&mut self, x: usize, nn: u8) {
+        self.advance(2);
 
-        self.v[x] = rand::random::<u8>() & nn;
+        self.v[x] = self.rng.next_byte() & nn;
     }
 
-    fn op_draw(&mut self, opcode: u16) {
-        self.pc += 2;
-        let s = ((opcode & 0x0F00) >> 8) as usize;
-        let t = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as usize;
+    fn op_draw(&mut self, s: usize, t: usize, n: usize, opcode: u16) -> Result<(), Chip8Error> {
+        // Dxy0 desenha um sprite 16x16 do SUPER-CHIP
+        let (columns, rows) = if n == 0 { (16, 16) } else { (8, n) };
+        let row_bytes = columns / 8;
+        let sprite_size = rows * row_bytes;
+        let planes = self.selected_planes().collect::<Vec<_>>();
+
+        self.check_memory(opcode, self.i as usize, sprite_size * planes.len())?;
+        self.advance(2);
 
-        let x = self.v[s] as usize;
-        let y = self.v[t] as usize;
+        let width = self.display_width();
+        let height = self.display_height();
+        let x = self.v[s] as usize % width;
+        let y = self.v[t] as usize % height;
 
         self.v[0xF] = 0;
 
-        for yline in 0..n {
-            let pixel = self.memory[(self.i + yline as u16) as usize];
-            for xline in 0..8 {
-                if (pixel & (0x80 >> xline)) != 0 {
-                    let x = (x + xline) % DISPLAY_WIDTH;
-                    let y = (y + yline) % DISPLAY_HEIGHT;
-                    let index = y * DISPLAY_WIDTH / 8 + x / 8;
-                    let mask = 0x80 >> (x % 8);
-                    if self.display[index] & mask != 0 {
-                        self.v[0xF] = 1;
+        // No XO-CHIP os dados de cada plano selecionado vêm em sequência
+        for (sprite, plane) in planes.into_iter().enumerate() {
+            let sprite_address = self.i as usize + sprite * sprite_size;
+
+            for yline in 0..rows {
+                if self.quirks.clip_sprites && y + yline >= height {
+                    break;
+                }
+
+                let address = sprite_address + yline * row_bytes;
+                let pixels = if row_bytes == 2 {
+                    (self.read_sprite(address) as u16) << 8 | self.read_sprite(address + 1) as u16
+                } else {
+                    (self.read_sprite(address) as u16) << 8
+                };
+
+                for xline in 0..columns {
+                    if self.quirks.clip_sprites && x + xline >= width {
+                        break;
+                    }
+
+                    if (pixels & (0x8000 >> xline)) != 0 {
+                        let x = (x + xline) % width;
+                        let y = (y + yline) % height;
+                        let on = self.plane_pixel(plane, x, y);
+
+                        if on {
+                            self.v[0xF] = 1;
+                        }
+
+                        self.set_plane_pixel(plane, x, y, !on);
                     }
-                    self.display[index] ^= mask;
                 }
             }
         }
+
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+        }
+
+        Ok(())
     }
 
-    fn op_spr(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let is_pressed = self.keypad & (1 << self.v[x]) != 0;
+    fn op_spr(&mut self, x: usize) {
+        self.advance(2);
+        // Só o nibble baixo de Vx indica a tecla
+        let is_pressed = self.keypad & (1 << (self.v[x] & 0xF)) != 0;
 
         if is_pressed {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_skup(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let is_pressed = self.keypad & (1 << self.v[x]) != 0;
+    fn op_skup(&mut self, x: usize) {
+        self.advance(2);
+        let is_pressed = self.keypad & (1 << (self.v[x] & 0xF)) != 0;
 
         if !is_pressed {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn op_moved(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_moved(&mut self, x: usize) {
+        self.advance(2);
 
         self.v[x] = self.delay_timer;
     }
 
-    fn op_keyd(&mut self, opcode: u16) {
+    fn op_keyd(&mut self, x: usize) {
         if self.old_keypad == self.keypad {
             return;
         }
 
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-
-        self.pc += 2;
+        self.advance(2);
 
         for i in 0..16 {
             if self.keypad & (1 << i) != 0 {
@@ -399,74 +1143,218 @@ impl Chip8 {
         }
     }
 
-    fn op_loadd(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_loadd(&mut self, x: usize) {
+        self.advance(2);
 
         self.delay_timer = self.v[x];
     }
 
-    fn op_loads(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_loads(&mut self, x: usize) {
+        self.advance(2);
 
         self.sound_timer = self.v[x];
     }
 
-    fn op_addi(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_addi(&mut self, x: usize) {
+        self.advance(2);
+
+        self.i = self.i.wrapping_add(self.v[x] as u16);
+    }
+
+    fn op_ldspr(&mut self, x: usize) {
+        self.advance(2);
 
-        self.i += self.v[x] as u16;
+        self.i = (FONT_ADDRESS + (self.v[x] & 0xF) as usize * FONT_GLYPH_SIZE) as u16;
     }
 
-    fn op_ldspr(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_ldhspr(&mut self, x: usize) {
+        self.advance(2);
 
-        self.i = self.v[x] as u16 * 5;
+        self.i = (BIG_FONT_ADDRESS + (self.v[x] & 0xF) as usize * BIG_FONT_GLYPH_SIZE) as u16;
     }
 
-    fn op_bcd(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_bcd(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
         let mut value = self.v[x];
 
+        self.check_memory(opcode, self.i as usize, 3)?;
+        self.advance(2);
+
         for i in 0..3 {
-            self.memory[(self.i + 2 - i) as usize] = value % 10;
+            self.write(self.i as usize + 2 - i, value % 10);
             value /= 10;
         }
+
+        Ok(())
     }
 
-    fn op_stor(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_stor(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
+        self.check_memory(opcode, self.i as usize, x + 1)?;
+        self.advance(2);
 
         for i in 0..=x {
-            self.memory[self.i as usize + i] = self.v[i];
+            self.write(self.i as usize + i, self.v[i]);
         }
+
+        self.increment_i_after_load_store(x);
+
+        Ok(())
     }
 
-    fn op_read(&mut self, opcode: u16) {
-        self.pc += 2;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn op_read(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
+        self.check_memory(opcode, self.i as usize, x + 1)?;
+        self.advance(2);
 
         for i in 0..=x {
-            self.v[i] = self.memory[self.i as usize + i];
+            self.v[i] = self.read(self.i as usize + i, Access::Read);
         }
+
+        self.increment_i_after_load_store(x);
+
+        Ok(())
+    }
+
+    fn increment_i_after_load_store(&mut self, x: usize) {
+        self.i = match self.quirks.load_store_increment {
+            LoadStoreIncrement::None => self.i,
+            LoadStoreIncrement::X => self.i.wrapping_add(x as u16),
+            LoadStoreIncrement::XPlus1 => self.i.wrapping_add(x as u16 + 1),
+        };
+    }
+
+    fn op_srpl(&mut self, x: usize) {
+        self.advance(2);
+
+        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+    }
+
+    fn op_lrpl(&mut self, x: usize) {
+        self.advance(2);
+
+        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
     }
 
-    pub fn op_scrd(&mut self, opcode: u16) {
-        self.pc += 2;
-        let n = (opcode & 0x000F) as usize;
-        for i in 0..n {
-            self.display[i] = 0;
+    fn op_scrd(&mut self, n: usize) {
+        self.advance(2);
+        self.scroll(0, n as isize);
+    }
+
+    fn op_scrr(&mut self) {
+        self.advance(2);
+        self.scroll(4, 0);
+    }
+
+    fn op_scrl(&mut self) {
+        self.advance(2);
+        self.scroll(-4, 0);
+    }
+
+    fn op_exit(&mut self) {
+        self.advance(2);
+        self.halt();
+    }
+
+    fn op_low(&mut self) {
+        self.advance(2);
+        self.hires = false;
+        self.display = [[0; DISPLAY_SIZE]; PLANES];
+    }
+
+    fn op_high(&mut self) {
+        self.advance(2);
+        self.hires = true;
+        self.display = [[0; DISPLAY_SIZE]; PLANES];
+    }
+
+    fn op_save(&mut self, x: usize, y: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let count = x.abs_diff(y) + 1;
+
+        self.check_memory(opcode, self.i as usize, count)?;
+        self.advance(2);
+
+        for offset in 0..count {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.write(self.i as usize + offset, self.v[register]);
+        }
+
+        Ok(())
+    }
+
+    fn op_restore(&mut self, x: usize, y: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let count = x.abs_diff(y) + 1;
+
+        self.check_memory(opcode, self.i as usize, count)?;
+        self.advance(2);
+
+        for offset in 0..count {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.v[register] = self.read(self.i as usize + offset, Access::Read);
+        }
+
+        Ok(())
+    }
+
+    fn op_loadil(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let address = self.pc as usize + 2;
+
+        self.check_memory(opcode, address, 2)?;
+        self.advance(4);
+        self.i = (self.read(address, Access::Execute) as u16) << 8
+            | self.read(address + 1, Access::Execute) as u16;
+        Ok(())
+    }
+
+    fn op_plane(&mut self, n: u8) {
+        self.advance(2);
+        self.planes = n & 0x3;
+    }
+
+    fn op_audio(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let start = self.i as usize;
+
+        self.check_memory(opcode, start, AUDIO_PATTERN_SIZE)?;
+        self.advance(2);
+
+        for i in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[i] = self.read(start + i, Access::Read);
+        }
+        Ok(())
+    }
+
+    fn op_pitch(&mut self, x: usize) {
+        self.advance(2);
+
+        self.pitch = self.v[x];
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.display_width() as isize;
+        let height = self.display_height() as isize;
+        let planes = self.selected_planes().collect::<Vec<_>>();
+
+        for plane in planes {
+            let previous = self.display[plane];
+
+            for y in 0..height {
+                for x in 0..width {
+                    let (from_x, from_y) = (x - dx, y - dy);
+                    let on = from_x >= 0 && from_x < width && from_y >= 0 && from_y < height && {
+                        let index = (from_y * width / 8 + from_x / 8) as usize;
+                        previous[index] & (0x80 >> (from_x % 8)) != 0
+                    };
+
+                    self.set_plane_pixel(plane, x as usize, y as usize, on);
+                }
+            }
         }
     }
 
     pub fn set_keypad(&mut self, keypad: u16) {
         self.old_keypad = self.keypad;
         self.keypad = keypad;
+
+        if let Some(movie) = &mut self.movie {
+            movie.record_input(self.frame, keypad);
+        }
     }
 
     pub fn on_key_down(&mut self, keys: u16) {
@@ -477,3 +1365,820 @@ impl Chip8 {
         self.set_keypad(self.keypad & !keys);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRITE: usize = 0x300;
+
+    fn machine(quirks: Quirks) -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(quirks);
+        cpu.set_random_source(RandomMode::Xorshift.source(1));
+        cpu
+    }
+
+    fn run(cpu: &mut Chip8, opcode: u16) {
+        cpu.execute(decode(opcode)).unwrap();
+    }
+
+    fn lit(cpu: &Chip8) -> Vec<(usize, usize)> {
+        let width = cpu.display_width();
+
+        cpu.framebuffer()
+            .iter()
+            .enumerate()
+            .filter(|(_, &pixel)| pixel != 0)
+            .map(|(i, _)| (i % width, i / width))
+            .collect()
+    }
+
+    #[test]
+    fn op_sys() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0x0123);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn op_clr() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.display[0][0] = 0xFF;
+        run(&mut cpu, 0x00E0);
+        assert!(lit(&cpu).is_empty());
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn op_call_and_op_rts() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0x2400);
+        assert_eq!((cpu.pc, cpu.sp, cpu.stack[0]), (0x400, 1, 0x202));
+
+        run(&mut cpu, 0x00EE);
+        assert_eq!((cpu.pc, cpu.sp), (0x202, 0));
+    }
+
+    #[test]
+    fn op_rts_underflow() {
+        let mut cpu = machine(Quirks::NONE);
+        let error = cpu.execute(decode(0x00EE)).unwrap_err();
+        assert!(matches!(error, Chip8Error::StackUnderflow { .. }));
+    }
+
+    #[test]
+    fn op_call_overflow() {
+        let mut cpu = machine(Quirks::NONE);
+
+        for _ in 0..STACK_SIZE {
+            run(&mut cpu, 0x2200);
+        }
+
+        let error = cpu.execute(decode(0x2200)).unwrap_err();
+        assert!(matches!(error, Chip8Error::StackOverflow { .. }));
+        assert_eq!(cpu.sp as usize, STACK_SIZE);
+    }
+
+    #[test]
+    fn op_jmp() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0x1ABC);
+        assert_eq!(cpu.pc, 0xABC);
+    }
+
+    #[test]
+    fn op_ske() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[3] = 0x42;
+        run(&mut cpu, 0x3342);
+        assert_eq!(cpu.pc, 0x204);
+        run(&mut cpu, 0x3343);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_skne() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[3] = 0x42;
+        run(&mut cpu, 0x4342);
+        assert_eq!(cpu.pc, 0x202);
+        run(&mut cpu, 0x4343);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_skre() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[1] = 7;
+        cpu.v[2] = 7;
+        run(&mut cpu, 0x5120);
+        assert_eq!(cpu.pc, 0x204);
+        cpu.v[2] = 8;
+        run(&mut cpu, 0x5120);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_skrne() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[1] = 7;
+        cpu.v[2] = 7;
+        run(&mut cpu, 0x9120);
+        assert_eq!(cpu.pc, 0x202);
+        cpu.v[2] = 8;
+        run(&mut cpu, 0x9120);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn skips_over_long_load_on_xo_chip() {
+        let mut cpu = machine(Quirks::XO_CHIP);
+        cpu.set_xo_chip(true);
+        cpu.memory[0x202..0x206].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        run(&mut cpu, 0x3000);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_load() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0x6A5C);
+        assert_eq!(cpu.v[0xA], 0x5C);
+    }
+
+    #[test]
+    fn op_add_wraps_without_touching_vf() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0xFF;
+        cpu.v[0xF] = 0x55;
+        run(&mut cpu, 0x7002);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x01, 0x55));
+    }
+
+    #[test]
+    fn op_move() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[4] = 9;
+        run(&mut cpu, 0x8340);
+        assert_eq!(cpu.v[3], 9);
+    }
+
+    #[test]
+    fn op_or_and_xor() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0xF] = 1;
+
+        cpu.v[0] = 0b1100;
+        cpu.v[1] = 0b1010;
+        run(&mut cpu, 0x8011);
+        assert_eq!(cpu.v[0], 0b1110);
+
+        cpu.v[0] = 0b1100;
+        run(&mut cpu, 0x8012);
+        assert_eq!(cpu.v[0], 0b1000);
+
+        cpu.v[0] = 0b1100;
+        run(&mut cpu, 0x8013);
+        assert_eq!(cpu.v[0], 0b0110);
+
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn logic_ops_reset_vf_with_quirk() {
+        for opcode in [0x8011, 0x8012, 0x8013] {
+            let mut cpu = machine(Quirks {
+                vf_reset: true,
+                ..Quirks::NONE
+            });
+            cpu.v[0xF] = 1;
+            run(&mut cpu, opcode);
+            assert_eq!(cpu.v[0xF], 0, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn op_addr_sets_carry() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0xF0;
+        cpu.v[1] = 0x20;
+        run(&mut cpu, 0x8014);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x10, 1));
+
+        cpu.v[1] = 0x01;
+        run(&mut cpu, 0x8014);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x11, 0));
+    }
+
+    #[test]
+    fn op_addr_into_vf_keeps_flag() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0xF] = 0xFF;
+        cpu.v[1] = 0x02;
+        run(&mut cpu, 0x8F14);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn op_sub_sets_not_borrow() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 5;
+        cpu.v[1] = 3;
+        run(&mut cpu, 0x8015);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (2, 1));
+
+        cpu.v[1] = 3;
+        run(&mut cpu, 0x8015);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0xFF, 0));
+
+        // Equal operands don't borrow
+        cpu.v[0] = 3;
+        run(&mut cpu, 0x8015);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0, 1));
+    }
+
+    #[test]
+    fn op_sub_into_vf_keeps_flag() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0xF] = 1;
+        cpu.v[1] = 2;
+        run(&mut cpu, 0x8F15);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn op_subn_sets_not_borrow() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 3;
+        cpu.v[1] = 5;
+        run(&mut cpu, 0x8017);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (2, 1));
+
+        cpu.v[0] = 6;
+        run(&mut cpu, 0x8017);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0xFF, 0));
+    }
+
+    #[test]
+    fn op_subn_into_vf_keeps_flag() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0xF] = 1;
+        cpu.v[1] = 5;
+        run(&mut cpu, 0x8F17);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn op_shr() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0b101;
+        cpu.v[1] = 0b110;
+        run(&mut cpu, 0x8016);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0b10, 1));
+
+        let mut cpu = machine(Quirks {
+            shift_uses_vy: true,
+            ..Quirks::NONE
+        });
+        cpu.v[0] = 0b101;
+        cpu.v[1] = 0b110;
+        run(&mut cpu, 0x8016);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0b11, 0));
+    }
+
+    #[test]
+    fn op_shl() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0x81;
+        cpu.v[1] = 0x01;
+        run(&mut cpu, 0x801E);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x02, 1));
+
+        let mut cpu = machine(Quirks {
+            shift_uses_vy: true,
+            ..Quirks::NONE
+        });
+        cpu.v[0] = 0x81;
+        cpu.v[1] = 0x01;
+        run(&mut cpu, 0x801E);
+        assert_eq!((cpu.v[0], cpu.v[0xF]), (0x02, 0));
+    }
+
+    #[test]
+    fn shifts_into_vf_keep_flag() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0xF] = 0x80;
+        run(&mut cpu, 0x8F0E);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.v[0xF] = 0x02;
+        run(&mut cpu, 0x8F06);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn op_loadi() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0xA123);
+        assert_eq!(cpu.i, 0x123);
+    }
+
+    #[test]
+    fn op_jumpi() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+        run(&mut cpu, 0xB300);
+        assert_eq!(cpu.pc, 0x310);
+
+        let mut cpu = machine(Quirks {
+            jump_uses_vx: true,
+            ..Quirks::NONE
+        });
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+        run(&mut cpu, 0xB300);
+        assert_eq!(cpu.pc, 0x320);
+    }
+
+    #[test]
+    fn op_rand_is_masked() {
+        let mut cpu = machine(Quirks::NONE);
+
+        for _ in 0..32 {
+            run(&mut cpu, 0xC00F);
+            assert_eq!(cpu.v[0] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn op_rand_resumes_from_save_state() {
+        for mode in [RandomMode::Xorshift, RandomMode::CosmacVip] {
+            let mut cpu = machine(Quirks::NONE);
+            cpu.set_random_source(mode.source(3));
+            // 0x200: RND V0, 0xFF; JP 0x200
+            cpu.load_rom(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+
+            let draw = |cpu: &mut Chip8| {
+                (0..16)
+                    .map(|_| {
+                        cpu.step().unwrap();
+                        cpu.step().unwrap();
+                        cpu.v[0]
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            draw(&mut cpu);
+            let state = cpu.save_state();
+            let next = draw(&mut cpu);
+
+            cpu.load_state(&state).unwrap();
+            assert_eq!(draw(&mut cpu), next, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn op_draw_sets_and_erases_pixels() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.memory[SPRITE] = 0b1100_0001;
+        cpu.i = SPRITE as u16;
+        cpu.v[0] = 2;
+        cpu.v[1] = 3;
+
+        run(&mut cpu, 0xD011);
+        assert_eq!(lit(&cpu), vec![(2, 3), (3, 3), (9, 3)]);
+        assert_eq!(cpu.v[0xF], 0);
+
+        run(&mut cpu, 0xD011);
+        assert!(lit(&cpu).is_empty());
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn op_draw_collision_only_on_overlap() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.memory[SPRITE] = 0x80;
+        cpu.i = SPRITE as u16;
+
+        run(&mut cpu, 0xD011);
+        cpu.v[0] = 1;
+        run(&mut cpu, 0xD011);
+        assert_eq!(cpu.v[0xF], 0);
+        assert_eq!(lit(&cpu), vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn op_draw_wraps_start_position() {
+        let mut cpu = machine(Quirks::COSMAC_VIP);
+        cpu.memory[SPRITE] = 0x80;
+        cpu.i = SPRITE as u16;
+        cpu.v[0] = LORES_WIDTH as u8 + 5;
+        cpu.v[1] = LORES_HEIGHT as u8 + 1;
+        run(&mut cpu, 0xD011);
+        assert_eq!(lit(&cpu), vec![(5, 1)]);
+    }
+
+    #[test]
+    fn op_draw_clips_or_wraps_at_edges() {
+        let sprite = [0xFF, 0xFF];
+
+        let mut cpu = machine(Quirks {
+            clip_sprites: true,
+            ..Quirks::NONE
+        });
+        cpu.memory[SPRITE..SPRITE + 2].copy_from_slice(&sprite);
+        cpu.i = SPRITE as u16;
+        cpu.v[0] = 60;
+        cpu.v[1] = 31;
+        run(&mut cpu, 0xD012);
+        assert_eq!(lit(&cpu), vec![(60, 31), (61, 31), (62, 31), (63, 31)]);
+
+        let mut cpu = machine(Quirks::NONE);
+        cpu.memory[SPRITE..SPRITE + 2].copy_from_slice(&sprite);
+        cpu.i = SPRITE as u16;
+        cpu.v[0] = 62;
+        cpu.v[1] = 31;
+        run(&mut cpu, 0xD012);
+        assert_eq!(lit(&cpu).len(), 16);
+        assert!(lit(&cpu).contains(&(0, 0)));
+        assert!(lit(&cpu).contains(&(63, 31)));
+    }
+
+    #[test]
+    fn op_draw_large_sprite_in_hires() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.memory[SPRITE..SPRITE + 32].fill(0xFF);
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0x00FF);
+        run(&mut cpu, 0xD010);
+        assert_eq!(lit(&cpu).len(), 256);
+        assert!(lit(&cpu).contains(&(15, 15)));
+    }
+
+    #[test]
+    fn op_draw_waits_for_vblank_with_quirk() {
+        let mut cpu = machine(Quirks {
+            display_wait: true,
+            ..Quirks::NONE
+        });
+        run(&mut cpu, 0xD011);
+        assert!(cpu.vblank_wait);
+    }
+
+    #[test]
+    fn op_draw_out_of_bounds() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.i = (MEMORY_SIZE - 2) as u16;
+        let error = cpu.execute(decode(0xD013)).unwrap_err();
+        assert!(matches!(error, Chip8Error::MemoryOutOfBounds { .. }));
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn op_spr() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0xA;
+        cpu.keypad = KeyMask::KeyA as u16;
+        run(&mut cpu, 0xE09E);
+        assert_eq!(cpu.pc, 0x204);
+
+        cpu.keypad = 0;
+        run(&mut cpu, 0xE09E);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_skup() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0xA;
+        cpu.keypad = KeyMask::KeyA as u16;
+        run(&mut cpu, 0xE0A1);
+        assert_eq!(cpu.pc, 0x202);
+
+        cpu.keypad = 0;
+        run(&mut cpu, 0xE0A1);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn key_skips_use_low_nibble() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0x1A;
+        cpu.keypad = KeyMask::KeyA as u16;
+        run(&mut cpu, 0xE09E);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn op_moved_and_op_loadd() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 30;
+        run(&mut cpu, 0xF015);
+        cpu.update_timers();
+        run(&mut cpu, 0xF107);
+        assert_eq!(cpu.v[1], 29);
+    }
+
+    #[test]
+    fn op_loads() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 30;
+        run(&mut cpu, 0xF018);
+        assert_eq!(cpu.sound_timer, 30);
+    }
+
+    #[test]
+    fn op_keyd_waits_for_key() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0xF30A);
+        assert_eq!(cpu.pc, 0x200);
+
+        cpu.on_key_down(KeyMask::Key7 as u16);
+        run(&mut cpu, 0xF30A);
+        assert_eq!((cpu.pc, cpu.v[3]), (0x202, 7));
+    }
+
+    #[test]
+    fn op_addi_leaves_vf() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.i = 0xFFF;
+        cpu.v[0] = 2;
+        run(&mut cpu, 0xF01E);
+        assert_eq!((cpu.i, cpu.v[0xF]), (0x1001, 0));
+    }
+
+    #[test]
+    fn op_ldspr_and_op_ldhspr() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 0x1B;
+        run(&mut cpu, 0xF029);
+        assert_eq!(cpu.i as usize, FONT_ADDRESS + 0xB * FONT_GLYPH_SIZE);
+
+        run(&mut cpu, 0xF030);
+        assert_eq!(cpu.i as usize, BIG_FONT_ADDRESS + 0xB * BIG_FONT_GLYPH_SIZE);
+    }
+
+    #[test]
+    fn op_bcd() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[0] = 254;
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0xF033);
+        assert_eq!(cpu.memory[SPRITE..SPRITE + 3], [2, 5, 4]);
+        assert_eq!(cpu.i as usize, SPRITE);
+    }
+
+    #[test]
+    fn op_bcd_out_of_bounds() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.i = (MEMORY_SIZE - 1) as u16;
+        assert!(cpu.execute(decode(0xF033)).is_err());
+    }
+
+    #[test]
+    fn op_stor_and_op_read() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0xF255);
+        assert_eq!(cpu.memory[SPRITE..SPRITE + 4], [1, 2, 3, 0]);
+        assert_eq!(cpu.i as usize, SPRITE);
+
+        cpu.v = [0; 16];
+        run(&mut cpu, 0xF265);
+        assert_eq!(cpu.v[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn load_store_increment_i_with_quirk() {
+        let mut cpu = machine(Quirks {
+            load_store_increment: LoadStoreIncrement::XPlus1,
+            ..Quirks::NONE
+        });
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0xF255);
+        assert_eq!(cpu.i as usize, SPRITE + 3);
+        run(&mut cpu, 0xF165);
+        assert_eq!(cpu.i as usize, SPRITE + 5);
+    }
+
+    #[test]
+    fn load_store_moves_i_as_each_preset_did() {
+        let presets = [
+            (Quirks::COSMAC_VIP, 3),
+            (Quirks::CHIP_48, 2),
+            (Quirks::SUPER_CHIP, 0),
+            (Quirks::XO_CHIP, 3),
+            (Quirks::NONE, 0),
+        ];
+
+        for (quirks, moved) in presets {
+            let mut cpu = machine(quirks);
+            cpu.i = SPRITE as u16;
+            run(&mut cpu, 0xF255);
+            assert_eq!(cpu.i as usize, SPRITE + moved, "Fx55 with {}", quirks);
+
+            cpu.i = SPRITE as u16;
+            run(&mut cpu, 0xF265);
+            assert_eq!(cpu.i as usize, SPRITE + moved, "Fx65 with {}", quirks);
+        }
+    }
+
+    #[test]
+    fn op_stor_out_of_bounds() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.i = (MEMORY_SIZE - 2) as u16;
+        assert!(cpu.execute(decode(0xF255)).is_err());
+        assert!(cpu.execute(decode(0xF265)).is_err());
+    }
+
+    #[test]
+    fn op_srpl_and_op_lrpl() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.v[..3].copy_from_slice(&[7, 8, 9]);
+        run(&mut cpu, 0xF175);
+        cpu.v = [0; 16];
+        run(&mut cpu, 0xF285);
+        assert_eq!(cpu.v[..3], [7, 8, 0]);
+    }
+
+    #[test]
+    fn op_scrd() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.display[0][0] = 0x80;
+        run(&mut cpu, 0x00C3);
+        assert_eq!(lit(&cpu), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn op_scrr_and_op_scrl() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.display[0][0] = 0x80;
+        run(&mut cpu, 0x00FB);
+        assert_eq!(lit(&cpu), vec![(4, 0)]);
+        run(&mut cpu, 0x00FC);
+        run(&mut cpu, 0x00FC);
+        assert!(lit(&cpu).is_empty());
+    }
+
+    #[test]
+    fn op_exit() {
+        let mut cpu = machine(Quirks::NONE);
+        run(&mut cpu, 0x00FD);
+        assert!(cpu.halted);
+    }
+
+    #[test]
+    fn op_high_and_op_low() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.display[0][0] = 0x80;
+        run(&mut cpu, 0x00FF);
+        assert_eq!(cpu.display_width(), HIRES_WIDTH);
+        assert!(lit(&cpu).is_empty());
+
+        cpu.display[0][0] = 0x80;
+        run(&mut cpu, 0x00FE);
+        assert_eq!(cpu.display_width(), LORES_WIDTH);
+        assert!(lit(&cpu).is_empty());
+    }
+
+    #[test]
+    fn op_save_and_op_restore() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.set_xo_chip(true);
+        cpu.v[2..5].copy_from_slice(&[1, 2, 3]);
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0x5242);
+        assert_eq!(cpu.memory[SPRITE..SPRITE + 3], [1, 2, 3]);
+
+        // A reversed range walks the registers backwards
+        run(&mut cpu, 0x5423);
+        assert_eq!(cpu.v[2..5], [3, 2, 1]);
+        assert_eq!(cpu.i as usize, SPRITE);
+    }
+
+    #[test]
+    fn op_loadil() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.set_xo_chip(true);
+        cpu.memory[0x200..0x204].copy_from_slice(&[0xF0, 0x00, 0xBE, 0xEF]);
+        cpu.emulate_cycle().unwrap();
+        assert_eq!((cpu.i, cpu.pc), (0xBEEF, 0x204));
+    }
+
+    #[test]
+    fn runs_opcode_at_end_of_xo_memory() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.set_xo_chip(true);
+        cpu.pc = 0xFFFE;
+        cpu.memory[0xFFFE..].copy_from_slice(&[0x60, 0x2A]);
+        cpu.emulate_cycle().unwrap();
+        assert_eq!((cpu.v[0], cpu.pc), (0x2A, 0x0000));
+
+        // SE V0, 0x2A skips over the end of memory too
+        cpu.pc = 0xFFFE;
+        cpu.memory[0xFFFE..].copy_from_slice(&[0x30, 0x2A]);
+        cpu.emulate_cycle().unwrap();
+        assert_eq!(cpu.pc, 0x0002);
+    }
+
+    #[test]
+    fn stops_at_end_of_memory() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.pc = 0xFFE;
+        cpu.memory[0xFFE..0x1000].copy_from_slice(&[0x60, 0x2A]);
+        cpu.emulate_cycle().unwrap();
+        assert!(matches!(
+            cpu.emulate_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                address: 0x1000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn op_plane() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.set_xo_chip(true);
+        cpu.memory[SPRITE..SPRITE + 2].copy_from_slice(&[0x80, 0x80]);
+        cpu.i = SPRITE as u16;
+        run(&mut cpu, 0xF301);
+        run(&mut cpu, 0xD011);
+        assert_eq!(cpu.pixel(0, 0), 3);
+
+        run(&mut cpu, 0xF201);
+        run(&mut cpu, 0x00E0);
+        assert_eq!(cpu.pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn op_audio_and_op_pitch() {
+        let mut cpu = machine(Quirks::NONE);
+        cpu.set_xo_chip(true);
+        cpu.memory[SPRITE..SPRITE + AUDIO_PATTERN_SIZE].fill(0xAA);
+        cpu.i = SPRITE as u16;
+        cpu.v[0] = 112;
+        run(&mut cpu, 0xF002);
+        run(&mut cpu, 0xF03A);
+        assert_eq!(cpu.audio_pattern(), [0xAA; AUDIO_PATTERN_SIZE]);
+        assert_eq!(cpu.pitch, 112);
+    }
+
+    #[test]
+    fn timers_drop_once_per_frame_at_any_speed() {
+        for cycles_per_frame in [1, 10, 100, 1000] {
+            let mut cpu = machine(Quirks::NONE);
+            cpu.set_cycles_per_frame(cycles_per_frame);
+            // 0x200: JP 0x200
+            cpu.load_rom(&[0x12, 0x00]).unwrap();
+            cpu.delay_timer = 5;
+            cpu.sound_timer = 3;
+
+            for frame in 1..=6 {
+                cpu.tick().unwrap();
+                assert_eq!(
+                    (cpu.frame(), cpu.delay_timer, cpu.sound_timer),
+                    (
+                        frame,
+                        5u8.saturating_sub(frame as u8),
+                        3u8.saturating_sub(frame as u8)
+                    ),
+                    "{} cycles per frame",
+                    cycles_per_frame
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn watchpoints_stop_after_the_accessing_instruction() {
+        let mut cpu = machine(Quirks::NONE);
+        // 0x200: LD I, 0x300; DRW V0, V0, 2
+        cpu.load_rom(&[0xA3, 0x00, 0xD0, 0x02]).unwrap();
+        cpu.add_watchpoint(Watchpoint {
+            start: 0x301,
+            end: 0x301,
+            read: true,
+            write: false,
+            execute: false,
+        });
+
+        assert!(!cpu.run_frame(|_| false).unwrap());
+        let hit = cpu.watch_hit().unwrap();
+        assert_eq!(
+            (hit.access, hit.address, hit.pc),
+            (Access::Read, 0x301, 0x202)
+        );
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn xo_chip_opcodes_need_xo_chip_mode() {
+        let mut cpu = machine(Quirks::NONE);
+        let error = cpu.execute(decode(0xF002)).unwrap_err();
+        assert!(matches!(error, Chip8Error::UnknownOpcode { .. }));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::chip8::DEFAULT_CYCLES_PER_FRAME;
+use crate::font::{BigFontSet, FontSet};
+use crate::quirks::Quirks;
+use crate::rng::RandomMode;
+
+/// Settings for a new machine, see [`Chip8::with_config`](crate::Chip8::with_config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub font: FontSet,
+    pub big_font: BigFontSet,
+    pub cycles_per_frame: u32,
+    pub quirks: Quirks,
+    pub xo_chip: bool,
+    pub random: RandomMode,
+    /// Seed for CXNN random numbers, `None` picks one at random.
+    pub seed: Option<u64>,
+    /// Frames kept for rewinding, 0 to disable.
+    pub rewind: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            font: FontSet::default(),
+            big_font: BigFontSet::default(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            quirks: Quirks::default(),
+            xo_chip: false,
+            random: RandomMode::default(),
+            seed: None,
+            rewind: 0,
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::disasm::ORIGIN;
+use crate::instruction::decode;
+
+pub const EXECUTED: u8 = 1;
+pub const SPRITE: u8 = 2;
+pub const READ: u8 = 4;
+pub const WRITTEN: u8 = 8;
+
+const FLAGS: [(u8, char); 4] = [(EXECUTED, 'x'), (SPRITE, 's'), (READ, 'r'), (WRITTEN, 'w')];
+const BYTES_PER_ROW: usize = 8;
+
+/// Remembers how each byte of memory was used: executed as an instruction,
+/// drawn as a sprite, read or written as data. Maps from several runs can be
+/// merged, and the text format keeps one `start-end flags` line per run of
+/// bytes used the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    bytes: Vec<u8>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage {
+            bytes: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn mark(&mut self, address: usize, flags: u8) {
+        if let Some(byte) = self.bytes.get_mut(address) {
+            *byte |= flags;
+        }
+    }
+
+    pub fn get(&self, address: usize) -> u8 {
+        self.bytes.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (byte, other) in self.bytes.iter_mut().zip(&other.bytes) {
+            *byte |= other;
+        }
+    }
+
+    /// Walks the ROM loaded at 0x200, showing executed bytes as instructions
+    /// and everything else as hex rows, each with its flags. Untouched bytes
+    /// show as `----`.
+    pub fn report(&self, rom: &[u8]) -> String {
+        let mut output = String::new();
+        let end = ORIGIN as usize + rom.len();
+        let byte = |address: usize| rom[address - ORIGIN as usize];
+
+        let mut totals = [0; 4];
+        let mut untouched = 0;
+
+        for address in ORIGIN as usize..end {
+            let flags = self.get(address);
+
+            if flags == 0 {
+                untouched += 1;
+            }
+
+            for (total, (flag, _)) in totals.iter_mut().zip(FLAGS) {
+                if flags & flag != 0 {
+                    *total += 1;
+                }
+            }
+        }
+
+        writeln!(
+            output,
+            "{} bytes: {} executed, {} sprite, {} read, {} written, {} untouched",
+            rom.len(),
+            totals[0],
+            totals[1],
+            totals[2],
+            totals[3],
+            untouched
+        )
+        .unwrap();
+        writeln!(output).unwrap();
+
+        let mut address = ORIGIN as usize;
+
+        while address < end {
+            let flags = self.get(address);
+
+            if flags & EXECUTED != 0 && address + 1 < end {
+                let opcode = u16::from_be_bytes([byte(address), byte(address + 1)]);
+                let flags = flags | self.get(address + 1);
+
+                writeln!(
+                    output,
+                    "{:04X}  {:<23}  {}  {}",
+                    address,
+                    format!("{:02X} {:02X}", byte(address), byte(address + 1)),
+                    flag_letters(flags),
+                    decode(opcode)
+                )
+                .unwrap();
+
+                address += 2;
+                continue;
+            }
+
+            let mut row = vec![];
+
+            while address + row.len() < end
+                && row.len() < BYTES_PER_ROW
+                && self.get(address + row.len()) == flags
+                && (flags & EXECUTED == 0 || address + row.len() + 1 >= end)
+            {
+                row.push(format!("{:02X}", byte(address + row.len())));
+            }
+
+            writeln!(
+                output,
+                "{:04X}  {:<23}  {}",
+                address,
+                row.join(" "),
+                flag_letters(flags)
+            )
+            .unwrap();
+
+            address += row.len();
+        }
+
+        output
+    }
+}
+
+fn flag_letters(flags: u8) -> String {
+    FLAGS
+        .iter()
+        .map(|&(flag, letter)| if flags & flag != 0 { letter } else { '-' })
+        .collect()
+}
+
+impl std::fmt::Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut address = 0;
+
+        while address < self.bytes.len() {
+            let flags = self.bytes[address];
+            let start = address;
+
+            while address < self.bytes.len() && self.bytes[address] == flags {
+                address += 1;
+            }
+
+            if flags != 0 {
+                writeln!(
+                    f,
+                    "{:04X}-{:04X} {}",
+                    start,
+                    address - 1,
+                    flag_letters(flags)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut coverage = Coverage::default();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = || format!("Invalid coverage on line {}: {}", number + 1, line);
+            let (range, letters) = line.split_once(' ').ok_or_else(error)?;
+            let (start, end) = range.split_once('-').ok_or_else(error)?;
+            let start = usize::from_str_radix(start, 16).map_err(|_| error())?;
+            let end = usize::from_str_radix(end, 16).map_err(|_| error())?;
+
+            if end < start || end >= coverage.bytes.len() {
+                return Err(error());
+            }
+
+            let mut flags = 0;
+
+            for letter in letters.chars().filter(|&c| c != '-') {
+                let (flag, _) = FLAGS
+                    .iter()
+                    .find(|&&(_, l)| l == letter)
+                    .ok_or_else(error)?;
+                flags |= flag;
+            }
+
+            for address in start..=end {
+                coverage.mark(address, flags);
+            }
+        }
+
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // 0x200: LD I, 0x20A; DRW V0, V0, 1; LD B, V0; JP 0x206; 0x208: unused;
+    // 0x20A: sprite
+    const ROM: [u8; 11] = [
+        0xA2, 0x0A, 0xD0, 0x01, 0xF0, 0x33, 0x12, 0x06, 0x00, 0x00, 0xFF,
+    ];
+
+    fn covered() -> Coverage {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&ROM).unwrap();
+        cpu.start_coverage(Coverage::default());
+
+        for _ in 0..4 {
+            cpu.emulate_cycle().unwrap();
+        }
+
+        cpu.stop_coverage().unwrap()
+    }
+
+    #[test]
+    fn marks_each_kind_of_access() {
+        let coverage = covered();
+
+        assert_eq!(coverage.get(0x200), EXECUTED);
+        assert_eq!(coverage.get(0x207), EXECUTED);
+        assert_eq!(coverage.get(0x208), 0);
+        assert_eq!(coverage.get(0x20A), SPRITE | WRITTEN);
+        assert_eq!(coverage.get(0x20B), WRITTEN);
+    }
+
+    #[test]
+    fn round_trips_and_merges() {
+        let coverage = covered();
+        let text = coverage.to_string();
+
+        assert_eq!(text, "0200-0207 x---\n020A-020A -s-w\n020B-020C ---w\n");
+        assert_eq!(text.parse::<Coverage>(), Ok(coverage.clone()));
+
+        let mut merged = "0208-0209 --r-".parse::<Coverage>().unwrap();
+        merged.merge(&coverage);
+        assert_eq!(merged.get(0x208), READ);
+        assert_eq!(merged.get(0x200), EXECUTED);
+
+        assert!("0200 x".parse::<Coverage>().is_err());
+        assert!("0200-0201 q".parse::<Coverage>().is_err());
+    }
+
+    #[test]
+    fn annotates_rom() {
+        assert_eq!(
+            covered().report(&ROM),
+            "11 bytes: 8 executed, 1 sprite, 0 read, 1 written, 2 untouched\n\
+             \n\
+             0200  A2 0A                    x---  LD I, 0x20A\n\
+             0202  D0 01                    x---  DRW V0, V0, 1\n\
+             0204  F0 33                    x---  LD B, V0\n\
+             0206  12 06                    x---  JP 0x206\n\
+             0208  00 00                    ----\n\
+             020A  FF                       -s-w\n"
+        );
+    }
+}
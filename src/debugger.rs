@@ -0,0 +1,584 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::{decode, Instruction};
+use crate::symbols::SymbolTable;
+use crate::watch::Watchpoint;
+
+const PROMPT: &str = "(chip8) ";
+const DISASM_LINES: usize = 10;
+
+const HELP: &str = "\
+step [n]              Run n instructions (default: 1)
+continue              Run until a breakpoint is hit
+pause                 Stop at the current instruction
+break [addr]          Set a breakpoint, or list them
+delete [addr]         Remove a breakpoint, or all of them
+watch [range] [rwx]   Pause on reads, writes or execution of addr or start-end
+                      (default: w), or list watchpoints
+unwatch [addr]        Remove the watchpoints at addr, or all of them
+regs                  Show the registers
+mem <addr> <len>      Dump memory
+stack                 Show the call stack
+disasm [addr] [n]     Disassemble n instructions (default: at PC)
+set <reg> <value>     Change v0..vf, i, pc, sp, dt or st
+Numbers are decimal unless prefixed with 0x or $. Addresses can also be
+given by the names in the symbol file.
+";
+
+/// Lines typed on stdin, read on their own thread so the debugger can poll
+/// them between frames.
+pub fn stdin_lines() -> Receiver<String> {
+    let (sender, lines) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    lines
+}
+
+/// Terminal debugger driven by the CPU thread. Commands are run between
+/// frames, so the window keeps showing the display while the machine is
+/// paused.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    paused: bool,
+    // Lets `continue` move past the breakpoint it stopped at
+    resuming: bool,
+    commands: Receiver<String>,
+    output: Box<dyn io::Write + Send>,
+    symbols: SymbolTable,
+}
+
+impl Debugger {
+    /// Takes commands one line at a time from `commands` and writes its
+    /// replies and prompts to `output`. The machine starts paused.
+    pub fn new(commands: Receiver<String>, output: impl io::Write + Send + 'static) -> Self {
+        let mut debugger = Debugger {
+            breakpoints: BTreeSet::new(),
+            paused: true,
+            resuming: false,
+            commands,
+            output: Box::new(output),
+            symbols: SymbolTable::default(),
+        };
+
+        debugger.print("Paused. Type 'help' for a list of commands.\n");
+        debugger.prompt();
+        debugger
+    }
+
+    /// Names to show for addresses, and to accept in their place.
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Runs the commands typed since the last frame, then the frame itself
+    /// unless the machine is paused.
+    pub fn frame(&mut self, cpu: &mut Chip8) -> Result<(), Chip8Error> {
+        while let Ok(line) = self.commands.try_recv() {
+            let output = self.command(cpu, &line)?;
+            self.print(&output);
+            self.prompt();
+        }
+
+        if self.paused {
+            return Ok(());
+        }
+
+        let breakpoints = &self.breakpoints;
+        let mut resuming = std::mem::take(&mut self.resuming);
+        let finished = cpu.run_frame(|cpu| {
+            let hit = !resuming && breakpoints.contains(&cpu.pc());
+            resuming = false;
+            hit
+        })?;
+
+        if !finished {
+            self.paused = true;
+
+            let stop = match cpu.watch_hit() {
+                Some(hit) => format!("\nWatchpoint: {}\n", hit),
+                None => format!("\nBreakpoint at {:#06X}\n", cpu.pc()),
+            };
+            let output = stop + &self.disasm(cpu, cpu.pc(), 1);
+            self.print(&output);
+            self.prompt();
+        }
+
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) {
+        self.output.write_all(text.as_bytes()).ok();
+    }
+
+    fn prompt(&mut self) {
+        self.print(PROMPT);
+        self.output.flush().ok();
+    }
+
+    fn command(&mut self, cpu: &mut Chip8, line: &str) -> Result<String, Chip8Error> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let symbols = &self.symbols;
+        let number = |i: usize| {
+            words
+                .get(i)
+                .map(|word| symbols.address(word).map_or_else(|| parse_number(word), Ok))
+        };
+
+        let output = match words[..] {
+            [] => String::new(),
+            ["help" | "h"] => HELP.to_string(),
+            ["step" | "s", ..] => match number(1).unwrap_or(Ok(1)) {
+                Ok(count) => {
+                    let mut output = String::new();
+                    self.paused = true;
+
+                    for _ in 0..count {
+                        cpu.step()?;
+
+                        if let Some(hit) = cpu.watch_hit() {
+                            writeln!(output, "Watchpoint: {}", hit).unwrap();
+                            break;
+                        }
+                    }
+
+                    output + &self.disasm(cpu, cpu.pc(), 1)
+                }
+                Err(e) => e,
+            },
+            ["continue" | "c"] => {
+                self.paused = false;
+                self.resuming = true;
+                "Continuing\n".to_string()
+            }
+            ["pause"] => {
+                self.paused = true;
+                self.disasm(cpu, cpu.pc(), 1)
+            }
+            ["break" | "b"] if self.breakpoints.is_empty() => "No breakpoints\n".to_string(),
+            ["break" | "b"] => self
+                .breakpoints
+                .iter()
+                .map(|address| format!("{:#06X}\n", address))
+                .collect(),
+            ["break" | "b", _] => match number(1).unwrap() {
+                Ok(address) => {
+                    self.breakpoints.insert(address);
+                    format!("Breakpoint set at {:#06X}\n", address)
+                }
+                Err(e) => e,
+            },
+            ["delete" | "d"] => {
+                self.breakpoints.clear();
+                "Deleted all breakpoints\n".to_string()
+            }
+            ["delete" | "d", _] => match number(1).unwrap() {
+                Ok(address) if self.breakpoints.remove(&address) => {
+                    format!("Deleted breakpoint at {:#06X}\n", address)
+                }
+                Ok(address) => format!("No breakpoint at {:#06X}\n", address),
+                Err(e) => e,
+            },
+            ["watch" | "w"] if cpu.watchpoints().is_empty() => "No watchpoints\n".to_string(),
+            ["watch" | "w"] => cpu
+                .watchpoints()
+                .iter()
+                .map(|watchpoint| format!("{}\n", watchpoint))
+                .collect(),
+            ["watch" | "w", range, ..] => {
+                match parse_watchpoint(range, words.get(2).copied().unwrap_or("w")) {
+                    Ok(watchpoint) => {
+                        cpu.add_watchpoint(watchpoint);
+                        format!("Watchpoint set at {}\n", watchpoint)
+                    }
+                    Err(e) => e,
+                }
+            }
+            ["unwatch"] => {
+                cpu.clear_watchpoints();
+                "Deleted all watchpoints\n".to_string()
+            }
+            ["unwatch", _] => match number(1).unwrap() {
+                Ok(address) if cpu.remove_watchpoints(|w| w.start == address) > 0 => {
+                    format!("Deleted watchpoints at {:#06X}\n", address)
+                }
+                Ok(address) => format!("No watchpoint at {:#06X}\n", address),
+                Err(e) => e,
+            },
+            ["regs" | "r"] => registers(cpu),
+            ["mem" | "m", _, _] => match (number(1).unwrap(), number(2).unwrap()) {
+                (Ok(address), Ok(len)) => dump(cpu.memory(), address, len),
+                (Err(e), _) | (_, Err(e)) => e,
+            },
+            ["stack"] => stack(cpu),
+            ["disasm", ..] => {
+                match (
+                    number(1).unwrap_or(Ok(cpu.pc())),
+                    number(2).unwrap_or(Ok(DISASM_LINES as u16)),
+                ) {
+                    (Ok(address), Ok(count)) => self.disasm(cpu, address, count as usize),
+                    (Err(e), _) | (_, Err(e)) => e,
+                }
+            }
+            ["set", register, value] => match parse_number(value) {
+                Ok(value) => set_register(cpu, register, value),
+                Err(e) => e,
+            },
+            _ => format!("Unknown command: {}\n", line.trim()),
+        };
+
+        Ok(output)
+    }
+
+    fn disasm(&self, cpu: &Chip8, mut address: u16, count: usize) -> String {
+        let memory = cpu.memory();
+        let word = |address: u16| {
+            let address = address as usize;
+            memory
+                .get(address..address + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let mut output = String::new();
+
+        for _ in 0..count {
+            let Some(opcode) = word(address) else {
+                break;
+            };
+            let instruction = decode(opcode);
+            let (raw, text) = match (instruction, word(address.wrapping_add(2))) {
+                (Instruction::LdILong, Some(target)) => (
+                    format!("F000 {:04X}", target),
+                    format!("LD I, LONG {:#06X}", target),
+                ),
+                _ => (format!("{:04X}", opcode), instruction.to_string()),
+            };
+            let text = match instruction {
+                Instruction::Jp(target)
+                | Instruction::Call(target)
+                | Instruction::JpV0(target)
+                | Instruction::LdI(target) => match self.symbols.name(target) {
+                    Some(name) => format!("{}  ; {}", text, name),
+                    None => text,
+                },
+                _ => text,
+            };
+            let current = if address == cpu.pc() { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&address) {
+                '*'
+            } else {
+                ' '
+            };
+
+            if let Some(name) = self.symbols.name(address) {
+                writeln!(output, "{}:", name).unwrap();
+            }
+
+            writeln!(
+                output,
+                "{}{} {:#06X}  {:<9}  {}",
+                current, breakpoint, address, raw, text
+            )
+            .unwrap();
+
+            address = match address.checked_add(instruction.size()) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        output
+    }
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let result = match text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+
+    result.map_err(|_| format!("Invalid number: {}\n", text))
+}
+
+/// Parses `addr` or `start-end` with access flags made of `r`, `w` and `x`.
+fn parse_watchpoint(range: &str, flags: &str) -> Result<Watchpoint, String> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+        None => (parse_number(range)?, parse_number(range)?),
+    };
+
+    if end < start {
+        return Err(format!("Invalid range: {}\n", range));
+    }
+
+    if flags.is_empty() || !flags.chars().all(|c| "rwx".contains(c)) {
+        return Err(format!("Invalid access flags: {}\n", flags));
+    }
+
+    Ok(Watchpoint {
+        start,
+        end,
+        read: flags.contains('r'),
+        write: flags.contains('w'),
+        execute: flags.contains('x'),
+    })
+}
+
+fn registers(cpu: &Chip8) -> String {
+    let registers = cpu.registers();
+    let mut output = String::new();
+
+    for (row, values) in registers.v.chunks(8).enumerate() {
+        let line = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X}={:02X}", row * 8 + i, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(output, "{}", line).unwrap();
+    }
+
+    writeln!(
+        output,
+        "I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+        registers.i, registers.pc, registers.sp, registers.delay_timer, registers.sound_timer
+    )
+    .unwrap();
+
+    output
+}
+
+fn stack(cpu: &Chip8) -> String {
+    let registers = cpu.registers();
+
+    if registers.sp == 0 {
+        return "Stack is empty\n".to_string();
+    }
+
+    registers.stack[..registers.sp as usize]
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, address)| format!("#{:<2} {:#06X}\n", i, address))
+        .collect()
+}
+
+fn dump(memory: &[u8], address: u16, len: u16) -> String {
+    let start = (address as usize).min(memory.len());
+    let end = (start + len as usize).min(memory.len());
+    let mut output = String::new();
+
+    for (i, line) in memory[start..end].chunks(16).enumerate() {
+        let bytes = line
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(output, "{:#06X}  {}", start + i * 16, bytes).unwrap();
+    }
+
+    output
+}
+
+fn set_register(cpu: &mut Chip8, name: &str, value: u16) -> String {
+    let mut registers = cpu.registers();
+    let name = name.to_lowercase();
+    let byte = u8::try_from(value);
+
+    let target = match name.as_str() {
+        "i" => {
+            registers.i = value;
+            Ok(())
+        }
+        "pc" => {
+            registers.pc = value;
+            Ok(())
+        }
+        "sp" if value as usize <= registers.stack.len() => byte.map(|b| registers.sp = b),
+        "sp" => return format!("SP must be at most {}\n", registers.stack.len()),
+        "dt" => byte.map(|b| registers.delay_timer = b),
+        "st" => byte.map(|b| registers.sound_timer = b),
+        _ => match name
+            .strip_prefix('v')
+            .and_then(|x| u8::from_str_radix(x, 16).ok())
+            .filter(|&x| x < 16)
+        {
+            Some(x) => byte.map(|b| registers.v[x as usize] = b),
+            None => return format!("Unknown register: {}\n", name),
+        },
+    };
+
+    match target {
+        Ok(()) => {
+            cpu.set_registers(registers);
+            format!("{} = {:#X}\n", name.to_uppercase(), value)
+        }
+        Err(_) => format!("Value out of range for {}\n", name.to_uppercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn debugger() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            paused: true,
+            resuming: false,
+            commands: mpsc::channel().1,
+            output: Box::new(io::sink()),
+            symbols: SymbolTable::default(),
+        }
+    }
+
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(rom).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_continues_past_them() {
+        // 0x200: ADD V0, 1; JP 0x200
+        let mut cpu = machine(&[0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = debugger();
+
+        debugger.command(&mut cpu, "break 0x202").unwrap();
+        debugger.command(&mut cpu, "continue").unwrap();
+        debugger.frame(&mut cpu).unwrap();
+        assert_eq!((cpu.pc(), cpu.registers().v[0]), (0x202, 1));
+
+        debugger.command(&mut cpu, "continue").unwrap();
+        debugger.frame(&mut cpu).unwrap();
+        assert_eq!((cpu.pc(), cpu.registers().v[0]), (0x202, 2));
+    }
+
+    /// Output that the test can still read after handing it over.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedOutput {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn answers_commands_on_its_output() {
+        // 0x200: ADD V0, 1; JP 0x200
+        let mut cpu = machine(&[0x70, 0x01, 0x12, 0x00]);
+        let (sender, commands) = mpsc::channel();
+        let output = SharedOutput::default();
+        let mut debugger = Debugger::new(commands, output.clone());
+
+        sender.send("break 0x202".to_string()).unwrap();
+        sender.send("continue".to_string()).unwrap();
+        debugger.frame(&mut cpu).unwrap();
+        debugger.frame(&mut cpu).unwrap();
+
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with("Paused. Type 'help' for a list of commands.\n(chip8) "));
+        assert!(text.contains("\nBreakpoint at 0x0202\n"));
+        assert!(text.ends_with("JP 0x200\n(chip8) "));
+    }
+
+    #[test]
+    fn steps_one_instruction() {
+        let mut cpu = machine(&[0x60, 0x05, 0x61, 0x06]);
+        let mut debugger = debugger();
+
+        let output = debugger.command(&mut cpu, "step").unwrap();
+        assert_eq!(cpu.pc(), 0x202);
+        assert!(output.contains("LD V1, 0x06"), "{}", output);
+        assert_eq!(cpu.registers().v[..2], [5, 0]);
+    }
+
+    #[test]
+    fn sets_registers() {
+        let mut cpu = machine(&[]);
+        let mut debugger = debugger();
+
+        debugger.command(&mut cpu, "set v3 0x10").unwrap();
+        debugger.command(&mut cpu, "set i $300").unwrap();
+        assert_eq!(cpu.registers().v[3], 0x10);
+        assert_eq!(cpu.registers().i, 0x300);
+
+        let output = debugger.command(&mut cpu, "set v3 256").unwrap();
+        assert_eq!(output, "Value out of range for V3\n");
+        let output = debugger.command(&mut cpu, "set vg 1").unwrap();
+        assert_eq!(output, "Unknown register: vg\n");
+    }
+
+    #[test]
+    fn pauses_on_watched_writes() {
+        // 0x200: LD I, 0x300; LD B, V0; JP 0x202
+        let mut cpu = machine(&[0xA3, 0x00, 0xF0, 0x33, 0x12, 0x02]);
+        let mut debugger = debugger();
+
+        debugger.command(&mut cpu, "watch 0x301-0x302 w").unwrap();
+        debugger.command(&mut cpu, "continue").unwrap();
+        debugger.frame(&mut cpu).unwrap();
+
+        let hit = cpu.watch_hit().unwrap();
+        assert_eq!((hit.address, hit.pc, hit.opcode), (0x302, 0x202, 0xF033));
+        assert_eq!(cpu.pc(), 0x204);
+        assert!(debugger.paused);
+
+        let output = debugger.command(&mut cpu, "watch 0x200 x").unwrap();
+        assert_eq!(output, "Watchpoint set at 0x0200 x\n");
+        let output = debugger.command(&mut cpu, "watch 0x300 q").unwrap();
+        assert_eq!(output, "Invalid access flags: q\n");
+    }
+
+    #[test]
+    fn dumps_memory() {
+        let mut cpu = machine(&[0x12, 0x34, 0x56]);
+        let mut debugger = debugger();
+
+        let output = debugger.command(&mut cpu, "mem 0x200 3").unwrap();
+        assert_eq!(output, "0x0200  12 34 56\n");
+    }
+
+    #[test]
+    fn uses_symbol_names() {
+        // 0x200: CALL 0x204; JP 0x200; 0x204: RET
+        let mut cpu = machine(&[0x22, 0x04, 0x12, 0x00, 0x00, 0xEE]);
+        let mut debugger = debugger();
+        debugger.set_symbols("0x0200 main\n0x0204 draw\n".parse().unwrap());
+
+        let output = debugger.command(&mut cpu, "break draw").unwrap();
+        assert_eq!(output, "Breakpoint set at 0x0204\n");
+
+        let output = debugger.command(&mut cpu, "disasm main 3").unwrap();
+        assert_eq!(
+            output,
+            "main:\n\
+             =>  0x0200  2204       CALL 0x204  ; draw\n\
+             \x20   0x0202  1200       JP 0x200  ; main\n\
+             draw:\n\
+             \x20 * 0x0204  00EE       RET\n"
+        );
+    }
+}
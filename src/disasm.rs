@@ -0,0 +1,455 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::instruction::{decode, Instruction};
+
+pub const ORIGIN: u16 = 0x200;
+
+const DATA_BYTES_PER_LINE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Subroutine,
+    Jump,
+    Data,
+}
+
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    code: BTreeSet<u16>,
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+impl<'a> Disassembly<'a> {
+    /// Traces every path reachable from the entry point, following jumps,
+    /// calls and both sides of each skip, so that whatever is never reached
+    /// is reported as data.
+    pub fn new(rom: &'a [u8]) -> Self {
+        let mut disassembly = Disassembly {
+            rom,
+            code: BTreeSet::new(),
+            labels: BTreeMap::new(),
+        };
+
+        disassembly.trace(ORIGIN);
+        disassembly
+    }
+
+    /// One past the last byte of the ROM, which is 0x10000 for the largest
+    /// XO-CHIP programs.
+    fn end(&self) -> u32 {
+        ORIGIN as u32 + self.rom.len() as u32
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        address >= ORIGIN && (address as u32) < self.end()
+    }
+
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = address.checked_sub(ORIGIN)? as usize;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    pub fn instruction(&self, address: u16) -> Option<Instruction> {
+        self.word(address).map(decode)
+    }
+
+    fn add_label(&mut self, address: u16, kind: LabelKind) {
+        let label = self.labels.entry(address).or_insert(kind);
+        *label = (*label).min(kind);
+    }
+
+    fn trace(&mut self, entry: u16) {
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            if self.code.contains(&address) {
+                continue;
+            }
+
+            let instruction = match self.instruction(address) {
+                Some(Instruction::Unknown(_)) | None => continue,
+                Some(instruction) => instruction,
+            };
+
+            if instruction.size() == 4 && self.word(address.wrapping_add(2)).is_none() {
+                continue;
+            }
+
+            self.code.insert(address);
+
+            // Nothing follows an instruction at the very end of memory
+            let Some(next) = address.checked_add(instruction.size()) else {
+                continue;
+            };
+
+            match instruction {
+                Instruction::Jp(target) => {
+                    self.add_label(target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Instruction::JpV0(target) => {
+                    self.add_label(target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Instruction::Call(target) => {
+                    self.add_label(target, LabelKind::Subroutine);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                Instruction::Ret | Instruction::Exit => {}
+                Instruction::LdI(target) => {
+                    self.add_label(target, LabelKind::Data);
+                    pending.push(next);
+                }
+                Instruction::LdILong => {
+                    if let Some(target) = self.word(address + 2) {
+                        self.add_label(target, LabelKind::Data);
+                    }
+                    pending.push(next);
+                }
+                instruction if instruction.is_skip() => {
+                    let skipped = self.instruction(next).map_or(2, |i| i.size());
+                    pending.push(next);
+                    pending.extend(next.checked_add(skipped));
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    fn is_code(&self, address: u16) -> bool {
+        self.code.contains(&address)
+    }
+
+    /// Addresses where a line of output starts, so only these can carry a
+    /// label. Targets that land in the middle of an instruction stay numeric.
+    fn line_starts(&self) -> BTreeSet<u16> {
+        let mut starts = BTreeSet::new();
+        let mut address = ORIGIN as u32;
+
+        while address < self.end() {
+            let at = address as u16;
+            starts.insert(at);
+
+            if self.is_code(at) {
+                address += self.instruction(at).map_or(2, |i| i.size()) as u32;
+            } else {
+                address += 1;
+            }
+        }
+
+        starts
+    }
+
+    fn label_name(&self, address: u16) -> String {
+        if address == ORIGIN {
+            return "main".to_string();
+        }
+
+        match self.labels.get(&address) {
+            Some(LabelKind::Subroutine) => format!("sub_{:04X}", address),
+            Some(LabelKind::Jump) => format!("label_{:04X}", address),
+            Some(LabelKind::Data) | None => format!("data_{:04X}", address),
+        }
+    }
+
+    fn target(&self, address: u16, starts: &BTreeSet<u16>) -> String {
+        if self.contains(address) && starts.contains(&address) {
+            self.label_name(address)
+        } else {
+            format!("{:#05X}", address)
+        }
+    }
+
+    fn data_run(&self, address: u16, starts: &BTreeSet<u16>) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut current = address as u32;
+
+        while current < self.end() && bytes.len() < DATA_BYTES_PER_LINE {
+            let at = current as u16;
+
+            if self.is_code(at)
+                || (at != address && self.labels.contains_key(&at) && starts.contains(&at))
+            {
+                break;
+            }
+
+            bytes.push(self.rom[(at - ORIGIN) as usize]);
+            current += 1;
+        }
+
+        bytes
+    }
+
+    fn has_label(&self, address: u16) -> bool {
+        address == ORIGIN || self.labels.contains_key(&address)
+    }
+
+    /// Lists each instruction and data run with its address and raw bytes.
+    pub fn listing(&self) -> String {
+        self.assembly(true)
+    }
+
+    /// Emits the program in the syntax of [`crate::asm`], keeping every byte
+    /// at its original address so the output reassembles to the same ROM.
+    pub fn source(&self) -> String {
+        self.assembly(false)
+    }
+
+    fn assembly(&self, columns: bool) -> String {
+        let starts = self.line_starts();
+        let mut output = String::new();
+        let mut next = ORIGIN as u32;
+
+        while next < self.end() {
+            let address = next as u16;
+            let line = |raw: &str, text: &str| {
+                if columns {
+                    format!("{:#06X}  {:<11}  {}", address, raw, text)
+                } else {
+                    format!("    {}", text)
+                }
+            };
+
+            if self.has_label(address) {
+                writeln!(output, "{}:", self.label_name(address)).unwrap();
+            }
+
+            if let Some(instruction) = self.instruction(address).filter(|_| self.is_code(address)) {
+                let (raw, text) = match instruction {
+                    Instruction::LdILong => {
+                        let target = self.word(address + 2).unwrap();
+                        (
+                            format!("F000 {:04X}", target),
+                            format!("LD I, LONG {}", self.target(target, &starts)),
+                        )
+                    }
+                    _ => (
+                        format!("{:04X}", instruction.encode()),
+                        self.mnemonic(instruction, &starts),
+                    ),
+                };
+
+                writeln!(output, "{}", line(&raw, &text)).unwrap();
+                next += instruction.size() as u32;
+            } else {
+                let bytes = self.data_run(address, &starts);
+                let raw = bytes
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let text = bytes
+                    .iter()
+                    .map(|b| format!("{:#04X}", b))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                writeln!(output, "{}", line(&raw, &format!("DB {}", text))).unwrap();
+                next += bytes.len() as u32;
+            }
+        }
+
+        output
+    }
+
+    fn mnemonic(&self, instruction: Instruction, starts: &BTreeSet<u16>) -> String {
+        match instruction {
+            Instruction::Jp(target) => format!("JP {}", self.target(target, starts)),
+            Instruction::Call(target) => format!("CALL {}", self.target(target, starts)),
+            Instruction::JpV0(target) => format!("JP V0, {}", self.target(target, starts)),
+            Instruction::LdI(target) => format!("LD I, {}", self.target(target, starts)),
+            instruction => instruction.to_string(),
+        }
+    }
+
+    /// Emits the program in Octo syntax, keeping every byte at its original
+    /// address so the output reassembles to the same ROM.
+    pub fn octo(&self) -> String {
+        let starts = self.line_starts();
+        let mut output = String::new();
+        let mut next = ORIGIN as u32;
+
+        while next < self.end() {
+            let address = next as u16;
+
+            if self.has_label(address) {
+                writeln!(output, ": {}", self.label_name(address)).unwrap();
+            }
+
+            if let Some(instruction) = self.instruction(address).filter(|_| self.is_code(address)) {
+                let text = match instruction {
+                    Instruction::LdILong => {
+                        let target = self.word(address + 2).unwrap();
+                        format!("i := long {}", self.target(target, &starts))
+                    }
+                    _ => self.octo_statement(instruction, &starts),
+                };
+
+                writeln!(output, "\t{}", text).unwrap();
+                next += instruction.size() as u32;
+            } else {
+                let bytes = self.data_run(address, &starts);
+                let text = bytes
+                    .iter()
+                    .map(|b| format!("{:#04X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                writeln!(output, "\t{}", text).unwrap();
+                next += bytes.len() as u32;
+            }
+        }
+
+        output
+    }
+
+    fn octo_statement(&self, instruction: Instruction, starts: &BTreeSet<u16>) -> String {
+        match instruction {
+            Instruction::Sys(_) | Instruction::Unknown(_) => {
+                let opcode = instruction.encode();
+                format!("{:#04X} {:#04X}", opcode >> 8, opcode & 0xFF)
+            }
+            Instruction::Cls => "clear".to_string(),
+            Instruction::Ret => "return".to_string(),
+            Instruction::Scd(n) => format!("scroll-down {}", n),
+            Instruction::Scr => "scroll-right".to_string(),
+            Instruction::Scl => "scroll-left".to_string(),
+            Instruction::Exit => "exit".to_string(),
+            Instruction::Low => "lores".to_string(),
+            Instruction::High => "hires".to_string(),
+            Instruction::Jp(target) => format!("jump {}", self.target(target, starts)),
+            Instruction::Call(target) => format!(":call {}", self.target(target, starts)),
+            Instruction::SeByte { x, byte } => format!("if v{:x} != {:#04X} then", x, byte),
+            Instruction::SneByte { x, byte } => format!("if v{:x} == {:#04X} then", x, byte),
+            Instruction::SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LdByte { x, byte } => format!("v{:x} := {:#04X}", x, byte),
+            Instruction::AddByte { x, byte } => format!("v{:x} += {:#04X}", x, byte),
+            Instruction::LdReg { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::LdI(target) => format!("i := {}", self.target(target, starts)),
+            Instruction::JpV0(target) => format!("jump0 {}", self.target(target, starts)),
+            Instruction::Rnd { x, byte } => format!("v{:x} := random {:#04X}", x, byte),
+            Instruction::Drw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::Skp(x) => format!("if v{:x} -key then", x),
+            Instruction::Sknp(x) => format!("if v{:x} key then", x),
+            Instruction::LdILong => "i := long 0".to_string(),
+            Instruction::Plane(n) => format!("plane {}", n),
+            Instruction::Audio => "audio".to_string(),
+            Instruction::LdVxDt(x) => format!("v{:x} := delay", x),
+            Instruction::LdVxK(x) => format!("v{:x} := key", x),
+            Instruction::LdDtVx(x) => format!("delay := v{:x}", x),
+            Instruction::LdStVx(x) => format!("buzzer := v{:x}", x),
+            Instruction::AddIVx(x) => format!("i += v{:x}", x),
+            Instruction::LdFVx(x) => format!("i := hex v{:x}", x),
+            Instruction::LdHfVx(x) => format!("i := bighex v{:x}", x),
+            Instruction::LdBVx(x) => format!("bcd v{:x}", x),
+            Instruction::Pitch(x) => format!("pitch := v{:x}", x),
+            Instruction::LdIVx(x) => format!("save v{:x}", x),
+            Instruction::LdVxI(x) => format!("load v{:x}", x),
+            Instruction::LdRVx(x) => format!("saveflags v{:x}", x),
+            Instruction::LdVxR(x) => format!("loadflags v{:x}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: CALL 0x208; LD I, 0x20A; JP 0x200; DB 0xFF, 0xFF
+    // 0x208: RET; 0x20A: DB 0x80
+    const PROGRAM: [u8; 11] = [
+        0x22, 0x08, 0xA2, 0x0A, 0x12, 0x00, 0xFF, 0xFF, 0x00, 0xEE, 0x80,
+    ];
+
+    #[test]
+    fn lists_code_and_data() {
+        let listing = Disassembly::new(&PROGRAM).listing();
+
+        assert_eq!(
+            listing,
+            "main:\n\
+             0x0200  2208         CALL sub_0208\n\
+             0x0202  A20A         LD I, data_020A\n\
+             0x0204  1200         JP main\n\
+             0x0206  FF FF        DB 0xFF, 0xFF\n\
+             sub_0208:\n\
+             0x0208  00EE         RET\n\
+             data_020A:\n\
+             0x020A  80           DB 0x80\n"
+        );
+    }
+
+    #[test]
+    fn emits_source_without_columns() {
+        let source = Disassembly::new(&PROGRAM).source();
+
+        assert_eq!(
+            source,
+            "main:\n\
+             \x20   CALL sub_0208\n\
+             \x20   LD I, data_020A\n\
+             \x20   JP main\n\
+             \x20   DB 0xFF, 0xFF\n\
+             sub_0208:\n\
+             \x20   RET\n\
+             data_020A:\n\
+             \x20   DB 0x80\n"
+        );
+    }
+
+    #[test]
+    fn follows_both_sides_of_a_skip() {
+        // 0x200: SE V0, 0; JP 0x206; JP 0x208
+        let rom = [0x30, 0x00, 0x12, 0x06, 0x12, 0x08, 0x00, 0xFD, 0x00, 0xFD];
+        let disassembly = Disassembly::new(&rom);
+
+        assert!((0x200..0x20A).step_by(2).all(|a| disassembly.is_code(a)));
+        assert_eq!(disassembly.labels.get(&0x206), Some(&LabelKind::Jump));
+        assert_eq!(disassembly.labels.get(&0x208), Some(&LabelKind::Jump));
+    }
+
+    #[test]
+    fn keeps_targets_inside_instructions_numeric() {
+        // 0x200: JP 0x201
+        let listing = Disassembly::new(&[0x12, 0x01]).listing();
+
+        assert!(listing.contains("JP 0x201"));
+        assert!(!listing.contains("label_0201"));
+    }
+
+    #[test]
+    fn lists_a_maximum_size_rom() {
+        // SYS 0x000 all the way up to a JP 0x200 at 0xFFFE
+        let mut rom = vec![0; 0x10000 - ORIGIN as usize];
+        let last = rom.len() - 2;
+        rom[last..].copy_from_slice(&[0x12, 0x00]);
+
+        let disassembly = Disassembly::new(&rom);
+
+        assert!(disassembly.is_code(0xFFFE));
+        assert!(disassembly
+            .listing()
+            .ends_with("0xFFFC  0000         SYS 0x000\n0xFFFE  1200         JP main\n"));
+        assert!(disassembly.octo().ends_with("\tjump main\n"));
+
+        // A skip at the last address has nowhere to go
+        rom[last..].copy_from_slice(&[0x30, 0x00]);
+        assert!(Disassembly::new(&rom)
+            .listing()
+            .ends_with("0xFFFE  3000         SE V0, 0x00\n"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode {
+        address: u16,
+        opcode: u16,
+    },
+    StackOverflow {
+        address: u16,
+        opcode: u16,
+    },
+    StackUnderflow {
+        address: u16,
+        opcode: u16,
+    },
+    MemoryOutOfBounds {
+        address: u16,
+        opcode: u16,
+        target: usize,
+    },
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { address, opcode } => {
+                write!(f, "Unknown opcode {:04X} at {:#05X}", opcode, address)
+            }
+            Chip8Error::StackOverflow { address, opcode } => {
+                write!(f, "Stack overflow by {:04X} at {:#05X}", opcode, address)
+            }
+            Chip8Error::StackUnderflow { address, opcode } => {
+                write!(f, "Stack underflow by {:04X} at {:#05X}", opcode, address)
+            }
+            Chip8Error::MemoryOutOfBounds {
+                address,
+                opcode,
+                target,
+            } => write!(
+                f,
+                "Memory access out of bounds ({:#X}) by {:04X} at {:#05X}",
+                target, opcode, address
+            ),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM too large: {} bytes (max: {} bytes)", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    InvalidHeader,
+    UnsupportedVersion(u16),
+    Corrupted,
+    RomMismatch,
+    SizeMismatch,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidHeader => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}", version)
+            }
+            StateError::Corrupted => write!(f, "Save state is corrupted"),
+            StateError::RomMismatch => write!(f, "Save state belongs to a different ROM"),
+            StateError::SizeMismatch => write!(f, "Save state has an unexpected size"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::chip8::{Chip8, FRAME_RATE};
+#[cfg(feature = "debugger")]
+use crate::debugger::Debugger;
+use crate::error::Chip8Error;
+#[cfg(feature = "debugger")]
+use crate::gdb::GdbStub;
+use crate::movie::{Desync, MoviePlayer};
+use crate::scheduler::FrameScheduler;
+
+/// What the display shows for one frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    /// Colour index of each pixel, row by row, as in [`Chip8::framebuffer`].
+    pub pixels: Vec<u8>,
+    /// The error that stopped the machine, until it is rewound.
+    pub error: Option<Chip8Error>,
+}
+
+/// What the speaker plays for one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sound {
+    pub beeping: bool,
+    /// XO-CHIP audio pattern and the rate it plays at, in bits per second.
+    pub pattern: [u8; 16],
+    pub rate: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// Keypad key from 0x0 to 0xF.
+    KeyDown(u8),
+    KeyUp(u8),
+    /// Starts or stops rewinding.
+    Rewind(bool),
+    SaveState(u8),
+    LoadState(u8),
+    Quit,
+}
+
+/// Something that happened while running, for the frontend to tell the
+/// user about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Crashed(Chip8Error),
+    MovieDesynced(Desync),
+    /// The movie ran out of input at this frame.
+    MovieFinished(u64),
+    StateSaved(u8),
+    StateLoaded(u8),
+    /// A state slot could not be saved or loaded.
+    StateFailed(String),
+    GdbConnected(SocketAddr),
+    GdbDisconnected,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Crashed(e) => write!(f, "{}", e),
+            Status::MovieDesynced(desync) => write!(f, "{}", desync),
+            Status::MovieFinished(frame) => write!(f, "Movie finished at frame {}", frame),
+            Status::StateSaved(slot) => write!(f, "Saved state to slot {}", slot),
+            Status::StateLoaded(slot) => write!(f, "Loaded state from slot {}", slot),
+            Status::StateFailed(message) => write!(f, "{}", message),
+            Status::GdbConnected(address) => write!(f, "GDB connected from {}", address),
+            Status::GdbDisconnected => write!(f, "GDB disconnected"),
+        }
+    }
+}
+
+pub trait Display {
+    fn draw(&mut self, screen: &Screen);
+
+    /// Shows a message once, when it happens. Frontends with nowhere to show
+    /// it can leave this out.
+    fn report(&mut self, _status: &Status) {}
+}
+
+pub trait AudioSink {
+    fn update(&mut self, sound: &Sound);
+}
+
+pub trait InputSource {
+    /// Events that happened since the last poll.
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+#[derive(Default)]
+pub struct RunOptions {
+    /// Movie whose input replaces the frontend's keys.
+    pub player: Option<MoviePlayer>,
+    /// Save states are kept next to the ROM, as `<rom_path>.state<slot>`.
+    pub rom_path: String,
+    #[cfg(feature = "debugger")]
+    pub debugger: Option<Debugger>,
+    #[cfg(feature = "debugger")]
+    pub gdb: Option<GdbStub>,
+}
+
+/// Runs the machine in real time on its own thread, while the calling thread
+/// polls `input` and feeds `display` and `audio` once per frame, until the
+/// input asks to quit. Returns the machine as it was left.
+///
+/// Anything worth telling the user, like a crash or the end of a movie, is
+/// passed to [`Display::report`] before the next frame is drawn.
+pub fn run(
+    chip8: Chip8,
+    options: RunOptions,
+    display: &mut impl Display,
+    audio: &mut impl AudioSink,
+    input: &mut impl InputSource,
+) -> Chip8 {
+    let RunOptions {
+        mut player,
+        rom_path,
+        #[cfg(feature = "debugger")]
+        mut debugger,
+        #[cfg(feature = "debugger")]
+        mut gdb,
+    } = options;
+    let playing = player.is_some();
+
+    let cpu = Arc::new(Mutex::new(chip8));
+    let scheduler = Arc::new(FrameScheduler::new(FRAME_RATE));
+    let crash: Arc<Mutex<Option<Chip8Error>>> = Arc::new(Mutex::new(None));
+    let rewinding = Arc::new(AtomicBool::new(false));
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+
+    let t1_cpu = cpu.clone();
+    let t1_scheduler = scheduler.clone();
+    let t1_crash = crash.clone();
+    let t1_rewinding = rewinding.clone();
+    let t1_statuses = statuses.clone();
+
+    let t1 = std::thread::spawn(move || {
+        t1_scheduler.run(|_| {
+            let mut cpu = t1_cpu.lock().unwrap();
+
+            // Rewinding also brings the machine back from a crash
+            if t1_rewinding.load(Ordering::Relaxed) {
+                if cpu.rewind(1) > 0 {
+                    t1_crash.lock().unwrap().take();
+                }
+                return true;
+            }
+
+            if let Some(player) = &mut player {
+                player.before_tick(&mut cpu);
+            }
+
+            #[cfg(feature = "debugger")]
+            let result = match (&mut debugger, &mut gdb) {
+                (Some(debugger), _) => debugger.frame(&mut cpu),
+                (None, Some(gdb)) => gdb.frame(&mut cpu),
+                (None, None) => cpu.tick(),
+            };
+            #[cfg(not(feature = "debugger"))]
+            let result = cpu.tick();
+            let mut statuses = t1_statuses.lock().unwrap();
+
+            #[cfg(feature = "debugger")]
+            if let Some(gdb) = &mut gdb {
+                statuses.extend(gdb.take_connection_changes());
+            }
+
+            if let Err(e) = result {
+                cpu.halt();
+                statuses.push(Status::Crashed(e.clone()));
+                t1_crash.lock().unwrap().replace(e);
+            }
+
+            if let Some(current) = &mut player {
+                if let Err(desync) = current.after_tick(&cpu) {
+                    statuses.push(Status::MovieDesynced(desync));
+                    player = None;
+                } else if current.finished() {
+                    statuses.push(Status::MovieFinished(cpu.frame()));
+                    player = None;
+                }
+            }
+
+            true
+        });
+    });
+
+    let mut frame = 0;
+
+    'running: loop {
+        for event in input.poll() {
+            match event {
+                InputEvent::Quit => {
+                    cpu.lock().unwrap().halt();
+                    scheduler.stop();
+                    break 'running;
+                }
+                InputEvent::Rewind(on) => rewinding.store(on, Ordering::Relaxed),
+                InputEvent::SaveState(slot) => display.report(&state_hotkey(
+                    &mut cpu.lock().unwrap(),
+                    &rom_path,
+                    slot,
+                    true,
+                )),
+                InputEvent::LoadState(slot) => display.report(&state_hotkey(
+                    &mut cpu.lock().unwrap(),
+                    &rom_path,
+                    slot,
+                    false,
+                )),
+                // Durante a reprodução de um filme a entrada vem do arquivo
+                InputEvent::KeyDown(key) if !playing => cpu.lock().unwrap().on_key_down(1 << key),
+                InputEvent::KeyUp(key) if !playing => cpu.lock().unwrap().on_key_up(1 << key),
+                InputEvent::KeyDown(_) | InputEvent::KeyUp(_) => {}
+            }
+        }
+
+        let reported = std::mem::take(&mut *statuses.lock().unwrap());
+        reported.iter().for_each(|status| display.report(status));
+
+        let error = crash.lock().unwrap().clone();
+        let (screen, sound) = {
+            let cpu = cpu.lock().unwrap();
+            let sound = Sound {
+                beeping: error.is_none() && cpu.sound_timer() > 0,
+                pattern: cpu.audio_pattern(),
+                rate: cpu.playback_rate(),
+            };
+            let screen = Screen {
+                width: cpu.display_width(),
+                height: cpu.display_height(),
+                pixels: cpu.framebuffer(),
+                error,
+            };
+
+            (screen, sound)
+        };
+
+        audio.update(&sound);
+        display.draw(&screen);
+
+        frame = scheduler.wait_frame(frame);
+    }
+
+    t1.join().unwrap();
+
+    match Arc::try_unwrap(cpu) {
+        Ok(cpu) => cpu.into_inner().unwrap(),
+        Err(_) => unreachable!("the CPU thread has finished"),
+    }
+}
+
+fn state_hotkey(cpu: &mut Chip8, rom: &str, slot: u8, save: bool) -> Status {
+    let path = format!("{}.state{}", rom, slot);
+
+    if save {
+        return match std::fs::write(&path, cpu.save_state()) {
+            Ok(()) => Status::StateSaved(slot),
+            Err(e) => Status::StateFailed(format!("Could not write {}: {}", path, e)),
+        };
+    }
+
+    match std::fs::read(&path) {
+        Ok(state) => match cpu.load_state(&state) {
+            Ok(()) => Status::StateLoaded(slot),
+            Err(e) => Status::StateFailed(format!("Could not load slot {}: {}", slot, e)),
+        },
+        Err(e) => Status::StateFailed(format!("Could not read {}: {}", path, e)),
+    }
+}
+
+/// Keeps the last screen drawn, for tests and tools without a window.
+#[derive(Debug, Default)]
+pub struct MemoryDisplay {
+    pub frames: u64,
+    pub screen: Option<Screen>,
+    pub statuses: Vec<Status>,
+}
+
+impl Display for MemoryDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        self.frames += 1;
+        self.screen = Some(screen.clone());
+    }
+
+    fn report(&mut self, status: &Status) {
+        self.statuses.push(status.clone());
+    }
+}
+
+/// Counts the frames the speaker would have been on.
+#[derive(Debug, Default)]
+pub struct MemoryAudio {
+    pub frames: u64,
+    pub beeping_frames: u64,
+}
+
+impl AudioSink for MemoryAudio {
+    fn update(&mut self, sound: &Sound) {
+        self.frames += 1;
+
+        if sound.beeping {
+            self.beeping_frames += 1;
+        }
+    }
+}
+
+/// Returns one list of events per poll, then quits.
+#[derive(Debug, Default)]
+pub struct ScriptedInput {
+    polls: VecDeque<Vec<InputEvent>>,
+}
+
+impl ScriptedInput {
+    pub fn new(polls: Vec<Vec<InputEvent>>) -> Self {
+        ScriptedInput {
+            polls: polls.into(),
+        }
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        self.polls
+            .pop_front()
+            .unwrap_or_else(|| vec![InputEvent::Quit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeds_frontend_until_quit() {
+        // 0x200: LD V0, K; LD ST, V0; JP 0x204
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0xF0, 0x0A, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+
+        let mut polls = vec![vec![InputEvent::KeyDown(5)], vec![], vec![]];
+        polls.push(vec![InputEvent::KeyUp(5)]);
+        polls.extend(std::iter::repeat_n(vec![], 4));
+
+        let mut display = MemoryDisplay::default();
+        let mut audio = MemoryAudio::default();
+        let mut input = ScriptedInput::new(polls);
+
+        let cpu = run(
+            cpu,
+            RunOptions::default(),
+            &mut display,
+            &mut audio,
+            &mut input,
+        );
+
+        assert_eq!(cpu.registers().v[0], 5);
+        assert!(cpu.halted());
+        assert_eq!(display.frames, 8);
+        assert_eq!(audio.frames, 8);
+        assert!(audio.beeping_frames > 0);
+
+        let screen = display.screen.unwrap();
+        assert_eq!((screen.width, screen.height), (64, 32));
+        assert_eq!(screen.pixels.len(), 64 * 32);
+        assert_eq!(screen.error, None);
+    }
+
+    #[test]
+    fn reports_crash_on_screen() {
+        // 0x200: RET
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x00, 0xEE]).unwrap();
+
+        let mut display = MemoryDisplay::default();
+        let mut input = ScriptedInput::new(vec![vec![]; 4]);
+
+        run(
+            cpu,
+            RunOptions::default(),
+            &mut display,
+            &mut MemoryAudio::default(),
+            &mut input,
+        );
+
+        let error = Chip8Error::StackUnderflow {
+            address: 0x200,
+            opcode: 0x00EE,
+        };
+        assert_eq!(display.screen.unwrap().error, Some(error.clone()));
+        assert_eq!(display.statuses, [Status::Crashed(error)]);
+    }
+
+    #[test]
+    fn reports_state_hotkeys() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        let rom_path = std::env::temp_dir()
+            .join(format!("chip8-frontend-{}.ch8", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+
+        assert!(matches!(
+            state_hotkey(&mut cpu, &rom_path, 1, false),
+            Status::StateFailed(message) if message.starts_with("Could not read ")
+        ));
+        assert_eq!(
+            state_hotkey(&mut cpu, &rom_path, 1, true),
+            Status::StateSaved(1)
+        );
+        assert_eq!(
+            state_hotkey(&mut cpu, &rom_path, 1, false),
+            Status::StateLoaded(1)
+        );
+        std::fs::remove_file(format!("{}.state1", rom_path)).unwrap();
+    }
+}
@@ -0,0 +1,707 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::frontend::Status;
+use crate::watch::{Access, WatchHit, Watchpoint};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// v0..vF, I, PC and SP
+const REGISTER_COUNT: usize = 19;
+const I_REGISTER: usize = 16;
+const PC_REGISTER: usize = 17;
+const SP_REGISTER: usize = 18;
+
+/// GDB remote serial protocol server on a local TCP port. Registers are
+/// numbered v0..vF (one byte each), then I and PC (two bytes, little
+/// endian) and SP (one byte). The machine waits for a client before it
+/// starts and runs freely again once the client detaches.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    running: bool,
+    // Lets `continue` move past the breakpoint it stopped at
+    resuming: bool,
+    connection_changes: Vec<Status>,
+}
+
+impl GdbStub {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(GdbStub {
+            listener,
+            client: None,
+            input: vec![],
+            breakpoints: BTreeSet::new(),
+            running: false,
+            resuming: false,
+            connection_changes: vec![],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves the packets received since the last frame, then runs the frame
+    /// unless the client has the machine stopped.
+    pub fn frame(&mut self, cpu: &mut Chip8) -> Result<(), Chip8Error> {
+        if self.client.is_none() {
+            self.accept();
+        }
+
+        if self.client.is_some() {
+            self.receive(cpu)?;
+        }
+
+        if !self.running {
+            return Ok(());
+        }
+
+        let breakpoints = &self.breakpoints;
+        let mut resuming = std::mem::take(&mut self.resuming);
+        let result = cpu.run_frame(|cpu| {
+            let hit = !resuming && breakpoints.contains(&cpu.pc());
+            resuming = false;
+            hit
+        });
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => {
+                self.running = false;
+                self.send(&stop_reply(cpu));
+            }
+            Err(e) => {
+                self.stop(signal(&e));
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clients that connected or went away since the last call.
+    pub fn take_connection_changes(&mut self) -> Vec<Status> {
+        std::mem::take(&mut self.connection_changes)
+    }
+
+    fn accept(&mut self) {
+        let Ok((stream, address)) = self.listener.accept() else {
+            return;
+        };
+
+        if stream.set_nonblocking(true).is_err() {
+            return;
+        }
+
+        self.connection_changes.push(Status::GdbConnected(address));
+        self.client = Some(stream);
+        self.input.clear();
+        self.running = false;
+    }
+
+    fn disconnect(&mut self) {
+        self.connection_changes.push(Status::GdbDisconnected);
+        self.client = None;
+        self.breakpoints.clear();
+        self.running = true;
+    }
+
+    fn receive(&mut self, cpu: &mut Chip8) -> Result<(), Chip8Error> {
+        let mut buffer = [0; 4096];
+
+        loop {
+            let Some(client) = &mut self.client else {
+                return Ok(());
+            };
+
+            match client.read(&mut buffer) {
+                Ok(0) => {
+                    self.disconnect();
+                    return Ok(());
+                }
+                Ok(len) => self.input.extend_from_slice(&buffer[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.disconnect();
+                    return Ok(());
+                }
+            }
+        }
+
+        while let Some(packet) = self.next_packet() {
+            let reply = match self.handle(cpu, &packet) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    self.stop(signal(&e));
+                    return Err(e);
+                }
+            };
+
+            if let Some(reply) = reply {
+                self.send(&reply);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the next complete packet out of the input, acknowledging it.
+    /// Interrupts (Ctrl-C) are returned as a packet of their own.
+    fn next_packet(&mut self) -> Option<String> {
+        loop {
+            match self.input.first()? {
+                0x03 => {
+                    self.input.remove(0);
+                    return Some("\x03".to_string());
+                }
+                b'$' => break,
+                // Acks and line noise
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+
+        let end = self.input.iter().position(|&b| b == b'#')?;
+
+        if self.input.len() < end + 3 {
+            return None;
+        }
+
+        let packet = self.input.drain(..end + 3).collect::<Vec<_>>();
+        let data = &packet[1..end];
+        let valid = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            == Some(checksum(data));
+
+        self.write(if valid { b"+" } else { b"-" });
+
+        if valid {
+            Some(String::from_utf8_lossy(data).into_owned())
+        } else {
+            None
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        if let Some(client) = &mut self.client {
+            if client.write_all(data).is_err() {
+                self.disconnect();
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.write(packet.as_bytes());
+    }
+
+    fn stop(&mut self, signal: u8) {
+        self.running = false;
+        self.send(&format!("S{:02x}", signal));
+    }
+
+    /// Answers one packet. `None` means no reply is due yet, as with
+    /// `continue`, which replies when the machine stops.
+    fn handle(&mut self, cpu: &mut Chip8, packet: &str) -> Result<Option<String>, Chip8Error> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match command {
+            "\x03" if self.running => {
+                self.stop(SIGINT);
+                return Ok(None);
+            }
+            "\x03" => return Ok(None),
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => encode(&registers(cpu)),
+            "G" => match decode(arguments) {
+                Some(bytes) if bytes.len() == register_bytes() => {
+                    set_registers(cpu, &bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(n) if n < REGISTER_COUNT => encode(&register(cpu, n)),
+                _ => "E01".to_string(),
+            },
+            "P" => match arguments
+                .split_once('=')
+                .and_then(|(n, value)| Some((usize::from_str_radix(n, 16).ok()?, decode(value)?)))
+            {
+                Some((n, value)) if n < REGISTER_COUNT && value.len() == register_size(n) => {
+                    let mut bytes = registers(cpu);
+                    let offset = register_offset(n);
+                    bytes[offset..offset + value.len()].copy_from_slice(&value);
+                    set_registers(cpu, &bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_range(arguments) {
+                Some((address, len)) if address < cpu.memory().len() => {
+                    match address.checked_add(len) {
+                        Some(end) => encode(&cpu.memory()[address..end.min(cpu.memory().len())]),
+                        None => "E01".to_string(),
+                    }
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => match arguments
+                .split_once(':')
+                .and_then(|(range, data)| Some((parse_range(range)?, decode(data)?)))
+            {
+                Some(((address, len), data))
+                    if data.len() == len
+                        && address
+                            .checked_add(len)
+                            .is_some_and(|end| end <= cpu.memory().len()) =>
+                {
+                    cpu.memory_mut()[address..address + len].copy_from_slice(&data);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "Z" | "z" => match parse_breakpoint(arguments) {
+                Some((0, address, _)) if command == "Z" => {
+                    self.breakpoints.insert(address);
+                    "OK".to_string()
+                }
+                Some((0, address, _)) => {
+                    self.breakpoints.remove(&address);
+                    "OK".to_string()
+                }
+                // Write, read and access watchpoints
+                Some((kind @ 2..=4, address, len)) if len > 0 => {
+                    let watchpoint = Watchpoint {
+                        start: address,
+                        end: address.saturating_add(len - 1),
+                        read: kind != 2,
+                        write: kind != 3,
+                        execute: false,
+                    };
+
+                    if command == "Z" {
+                        cpu.add_watchpoint(watchpoint);
+                    } else {
+                        cpu.remove_watchpoints(|w| *w == watchpoint);
+                    }
+
+                    "OK".to_string()
+                }
+                _ => String::new(),
+            },
+            "s" | "c" => {
+                if let Ok(address) = u16::from_str_radix(arguments, 16) {
+                    let mut registers = cpu.registers();
+                    registers.pc = address;
+                    cpu.set_registers(registers);
+                }
+
+                if command == "s" {
+                    cpu.step()?;
+                    stop_reply(cpu)
+                } else {
+                    self.running = true;
+                    self.resuming = true;
+                    return Ok(None);
+                }
+            }
+            "H" => "OK".to_string(),
+            "q" if arguments.starts_with("Supported") => {
+                "PacketSize=1000;qXfer:features:read+".to_string()
+            }
+            "q" if arguments.starts_with("Xfer:features:read:") => {
+                match arguments["Xfer:features:read:".len()..]
+                    .split_once(':')
+                    .and_then(|(annex, range)| Some((annex, parse_range(range)?)))
+                {
+                    Some(("target.xml", (offset, len))) => read_chunk(&target_xml(), offset, len),
+                    _ => "E01".to_string(),
+                }
+            }
+            "q" if arguments == "Attached" => "1".to_string(),
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return Ok(None);
+            }
+            "k" => {
+                cpu.halt();
+                self.disconnect();
+                return Ok(None);
+            }
+            _ => String::new(),
+        };
+
+        Ok(Some(reply))
+    }
+}
+
+/// Target description telling GDB the register layout, since it has no
+/// built-in CHIP-8 architecture.
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+
+    for n in 0..REGISTER_COUNT {
+        let (name, kind) = match n {
+            I_REGISTER => ("i".to_string(), "data_ptr"),
+            PC_REGISTER => ("pc".to_string(), "code_ptr"),
+            SP_REGISTER => ("sp".to_string(), "uint8"),
+            _ => (format!("v{:x}", n), "uint8"),
+        };
+
+        writeln!(
+            xml,
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+            name,
+            register_size(n) * 8,
+            kind,
+            n
+        )
+        .unwrap();
+    }
+
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+/// Reply to a `qXfer` read: `m` when more data follows, `l` for the last
+/// chunk.
+fn read_chunk(data: &str, offset: usize, len: usize) -> String {
+    let start = offset.min(data.len());
+    let end = start.saturating_add(len).min(data.len());
+    let more = if end < data.len() { 'm' } else { 'l' };
+
+    format!("{}{}", more, &data[start..end])
+}
+
+fn signal(error: &Chip8Error) -> u8 {
+    match error {
+        Chip8Error::UnknownOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        write!(hex, "{:02x}", b).unwrap();
+        hex
+    })
+}
+
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_range(arguments: &str) -> Option<(usize, usize)> {
+    let (address, len) = arguments.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Type, address and length of a `Ztype,addr,kind` breakpoint.
+fn parse_breakpoint(arguments: &str) -> Option<(u8, u16, u16)> {
+    let mut fields = arguments.split(',');
+
+    Some((
+        fields.next()?.parse().ok()?,
+        u16::from_str_radix(fields.next()?, 16).ok()?,
+        u16::from_str_radix(fields.next()?, 16).ok()?,
+    ))
+}
+
+/// Stop reply for a trap, naming the watched address when a watchpoint
+/// caused it.
+fn stop_reply(cpu: &Chip8) -> String {
+    let kind = match cpu.watch_hit() {
+        Some(WatchHit {
+            access: Access::Write,
+            address,
+            ..
+        }) => Some(("watch", address)),
+        Some(WatchHit {
+            access: Access::Read,
+            address,
+            ..
+        }) => Some(("rwatch", address)),
+        _ => None,
+    };
+
+    match kind {
+        Some((kind, address)) => format!("T{:02x}{}:{:x};", SIGTRAP, kind, address),
+        None => format!("S{:02x}", SIGTRAP),
+    }
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        I_REGISTER | PC_REGISTER => 2,
+        _ => 1,
+    }
+}
+
+fn register_offset(n: usize) -> usize {
+    (0..n).map(register_size).sum()
+}
+
+fn register_bytes() -> usize {
+    register_offset(REGISTER_COUNT)
+}
+
+fn registers(cpu: &Chip8) -> Vec<u8> {
+    let registers = cpu.registers();
+    let mut bytes = registers.v.to_vec();
+    bytes.extend_from_slice(&registers.i.to_le_bytes());
+    bytes.extend_from_slice(&registers.pc.to_le_bytes());
+    bytes.push(registers.sp);
+    bytes
+}
+
+fn register(cpu: &Chip8, n: usize) -> Vec<u8> {
+    let offset = register_offset(n);
+    registers(cpu)[offset..offset + register_size(n)].to_vec()
+}
+
+fn set_registers(cpu: &mut Chip8, bytes: &[u8]) {
+    let mut registers = cpu.registers();
+    registers.v.copy_from_slice(&bytes[..16]);
+    registers.i = u16::from_le_bytes([bytes[16], bytes[17]]);
+    registers.pc = u16::from_le_bytes([bytes[18], bytes[19]]);
+    registers.sp = bytes[register_offset(SP_REGISTER)];
+    cpu.set_registers(registers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Minimal RSP client that drives the stub from the test thread.
+    struct Client {
+        stream: TcpStream,
+        stub: GdbStub,
+        cpu: Chip8,
+    }
+
+    impl Client {
+        fn connect(rom: &[u8]) -> Client {
+            let mut stub = GdbStub::bind(0).unwrap();
+            let stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(1)))
+                .unwrap();
+
+            let mut cpu = Chip8::new();
+            cpu.load_rom(rom).unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while stub.client.is_none() {
+                assert!(Instant::now() < deadline, "stub never accepted");
+                stub.frame(&mut cpu).unwrap();
+            }
+
+            Client { stream, stub, cpu }
+        }
+
+        /// Sends a packet and runs frames until a reply arrives.
+        fn request(&mut self, data: &str) -> String {
+            let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut received = vec![];
+
+            loop {
+                assert!(Instant::now() < deadline, "no reply");
+                self.stub.frame(&mut self.cpu).unwrap();
+
+                let mut buffer = [0; 4096];
+                if let Ok(len) = self.stream.read(&mut buffer) {
+                    received.extend_from_slice(&buffer[..len]);
+                }
+
+                let text = String::from_utf8_lossy(&received).into_owned();
+                let text = text.trim_start_matches('+');
+
+                if let Some(end) = text.find('#').filter(|&end| text.len() >= end + 3) {
+                    assert!(text.starts_with('$'), "{}", text);
+                    let (data, sum) = (&text[1..end], &text[end + 1..end + 3]);
+                    assert_eq!(
+                        u8::from_str_radix(sum, 16).unwrap(),
+                        checksum(data.as_bytes())
+                    );
+                    self.stream.write_all(b"+").unwrap();
+                    return data.to_string();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut client = Client::connect(&[0x6A, 0x42]);
+
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("p11"), "0002");
+
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("pa"), "42");
+        assert_eq!(client.request("p11"), "0202");
+
+        assert_eq!(client.request("P10=3412"), "OK");
+        assert_eq!(client.cpu.registers().i, 0x1234);
+
+        let registers = client.request("g");
+        assert_eq!(registers.len(), register_bytes() * 2);
+        assert_eq!(&registers[32..36], "3412");
+
+        assert_eq!(
+            client.request(&format!("G{}", "01".repeat(register_bytes()))),
+            "OK"
+        );
+        assert_eq!(client.cpu.registers().pc, 0x0101);
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut client = Client::connect(&[0x12, 0x34]);
+
+        assert_eq!(client.request("m200,2"), "1234");
+        assert_eq!(client.request("M300,3:aabbcc"), "OK");
+        assert_eq!(client.request("m300,3"), "aabbcc");
+        assert_eq!(client.request("m1000,1"), "E01");
+        assert_eq!(client.request("Mfff,2:0000"), "E01");
+        assert_eq!(client.request("m200,ffffffffffffffff"), "E01");
+        assert_eq!(client.request("Mffffffffffffffff,1:00"), "E01");
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        // 0x200: ADD V0, 1; JP 0x200
+        let mut client = Client::connect(&[0x70, 0x01, 0x12, 0x00]);
+
+        assert_eq!(client.request("Z0,202,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.cpu.pc(), 0x202);
+
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.cpu.registers().v[0], 2);
+
+        assert_eq!(client.request("z0,202,2"), "OK");
+        client.stream.write_all(b"$c#63").unwrap();
+        client.stub.frame(&mut client.cpu).unwrap();
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(), "S02");
+    }
+
+    #[test]
+    fn reports_watchpoints() {
+        // 0x200: LD I, 0x300; LD [I], V1; LD V1, [I]; JP 0x200 (I stays at
+        // 0x300 with the default quirks)
+        let mut client = Client::connect(&[0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x00]);
+
+        assert_eq!(client.request("Z2,301,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:301;");
+        assert_eq!(client.cpu.pc(), 0x204);
+
+        assert_eq!(client.request("z2,301,1"), "OK");
+        assert_eq!(client.request("Z3,301,2"), "OK");
+        assert_eq!(client.request("s"), "T05rwatch:301;");
+    }
+
+    #[test]
+    fn ignores_unsupported_packets() {
+        let mut client = Client::connect(&[]);
+
+        assert_eq!(client.request("Z9,200,2"), "");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        assert_eq!(client.request("qXfer:features:read:other.xml:0,100"), "E01");
+    }
+
+    #[test]
+    fn reports_connections() {
+        let mut client = Client::connect(&[]);
+        let address = client.stream.local_addr().unwrap();
+
+        assert_eq!(
+            client.stub.take_connection_changes(),
+            [Status::GdbConnected(address)]
+        );
+        assert_eq!(client.stub.take_connection_changes(), []);
+
+        assert_eq!(client.request("D"), "OK");
+        assert_eq!(
+            client.stub.take_connection_changes(),
+            [Status::GdbDisconnected]
+        );
+    }
+
+    #[test]
+    fn describes_the_registers() {
+        let mut client = Client::connect(&[]);
+
+        assert_eq!(
+            client.request("qSupported:swbreak+"),
+            "PacketSize=1000;qXfer:features:read+"
+        );
+
+        let mut xml = String::new();
+
+        loop {
+            let reply = client.request(&format!(
+                "qXfer:features:read:target.xml:{:x},40",
+                xml.len()
+            ));
+            let (kind, chunk) = reply.split_at(1);
+            assert!(chunk.len() <= 0x40);
+            xml.push_str(chunk);
+
+            if kind == "l" {
+                break;
+            }
+
+            assert_eq!(kind, "m");
+        }
+
+        assert_eq!(xml, target_xml());
+        assert_eq!(xml.matches("<reg ").count(), REGISTER_COUNT);
+        assert!(xml.contains("<reg name=\"vf\" bitsize=\"8\" type=\"uint8\" regnum=\"15\"/>"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"17\"/>"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::movie::{Desync, MoviePlayer};
+
+/// Key presses and releases to feed into a headless run, one per line:
+/// `<frame> press <key>` or `<frame> release <key>`, with the key as a hex
+/// digit. Lines starting with `#` are comments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputScript {
+    events: Vec<(u64, bool, u16)>,
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = vec![];
+
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| format!("Line {}: {}", index + 1, message);
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            let [frame, action, key] = fields[..] else {
+                return Err(error("expected <frame> press|release <key>"));
+            };
+            let frame = frame
+                .parse::<u64>()
+                .map_err(|_| error("invalid frame number"))?;
+            let pressed = match action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(error("expected press or release")),
+            };
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(error("invalid key")),
+            };
+
+            events.push((frame, pressed, 1 << key));
+        }
+
+        events.sort_by_key(|&(frame, _, _)| frame);
+        Ok(InputScript { events })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunError {
+    Cpu(Chip8Error),
+    Desync(Desync),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Cpu(e) => write!(f, "{}", e),
+            RunError::Desync(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Runs `frames` frames as fast as possible, stopping at the first error.
+pub fn run(
+    cpu: &mut Chip8,
+    frames: u64,
+    script: &InputScript,
+    mut player: Option<MoviePlayer>,
+) -> Result<(), RunError> {
+    let mut events = script.events.iter().peekable();
+
+    for _ in 0..frames {
+        while let Some(&(_, pressed, keys)) = events.next_if(|&&(frame, _, _)| frame <= cpu.frame())
+        {
+            if pressed {
+                cpu.on_key_down(keys);
+            } else {
+                cpu.on_key_up(keys);
+            }
+        }
+
+        if let Some(player) = &mut player {
+            player.before_tick(cpu);
+        }
+
+        cpu.tick().map_err(RunError::Cpu)?;
+
+        if let Some(player) = &mut player {
+            player.after_tick(cpu).map_err(RunError::Desync)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn registers_json(cpu: &Chip8, error: Option<&RunError>) -> String {
+    let registers = cpu.registers();
+    let list = |values: Vec<String>| values.join(", ");
+    let mut json = String::new();
+
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"frame\": {},", cpu.frame()).unwrap();
+    writeln!(
+        json,
+        "  \"v\": [{}],",
+        list(registers.v.iter().map(|v| v.to_string()).collect())
+    )
+    .unwrap();
+    writeln!(json, "  \"i\": {},", registers.i).unwrap();
+    writeln!(json, "  \"pc\": {},", registers.pc).unwrap();
+    writeln!(json, "  \"sp\": {},", registers.sp).unwrap();
+    writeln!(
+        json,
+        "  \"stack\": [{}],",
+        list(
+            registers.stack[..registers.sp as usize]
+                .iter()
+                .map(|address| address.to_string())
+                .collect()
+        )
+    )
+    .unwrap();
+    writeln!(json, "  \"delay_timer\": {},", registers.delay_timer).unwrap();
+    writeln!(json, "  \"sound_timer\": {},", registers.sound_timer).unwrap();
+    writeln!(json, "  \"halted\": {},", cpu.halted()).unwrap();
+
+    match error {
+        Some(error) => writeln!(json, "  \"error\": {}", json_string(&error.to_string())).unwrap(),
+        None => writeln!(json, "  \"error\": null").unwrap(),
+    }
+
+    writeln!(json, "}}").unwrap();
+    json
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_input_scripts() {
+        let script =
+            "# hold 5, then tap A\n30 release 5\n10 press 5\n\n  40 press a\n41 release A\n"
+                .parse::<InputScript>()
+                .unwrap();
+
+        assert_eq!(
+            script.events,
+            [
+                (10, true, 1 << 5),
+                (30, false, 1 << 5),
+                (40, true, 1 << 0xA),
+                (41, false, 1 << 0xA)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_scripts() {
+        let error = |script: &str| script.parse::<InputScript>().unwrap_err();
+
+        assert_eq!(
+            error("10 press"),
+            "Line 1: expected <frame> press|release <key>"
+        );
+        assert_eq!(error("\nx press 1"), "Line 2: invalid frame number");
+        assert_eq!(error("1 hold 1"), "Line 1: expected press or release");
+        assert_eq!(error("1 press 10"), "Line 1: invalid key");
+    }
+
+    #[test]
+    fn feeds_the_script_into_the_machine() {
+        // 0x200: LD V0, K; LD I, 0x300; LD [I], V0; JP 0x206
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0xF0, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06])
+            .unwrap();
+        let script = "2 press 7\n4 release 7".parse::<InputScript>().unwrap();
+
+        assert_eq!(run(&mut cpu, 6, &script, None), Ok(()));
+        assert_eq!(cpu.memory()[0x300], 7);
+    }
+
+    #[test]
+    fn writes_registers_as_json() {
+        // 0x200: LD V1, 5; CALL 0x206; DW 0x5121; ADD V1, 1; RET
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x61, 0x05, 0x22, 0x06, 0x51, 0x21, 0x71, 0x01, 0x00, 0xEE])
+            .unwrap();
+        let error = run(&mut cpu, 1, &InputScript::default(), None).unwrap_err();
+
+        assert_eq!(
+            registers_json(&cpu, Some(&error)),
+            "{\n  \"frame\": 0,\n  \"v\": [0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],\n  \
+             \"i\": 0,\n  \"pc\": 516,\n  \"sp\": 0,\n  \"stack\": [],\n  \"delay_timer\": 0,\n  \
+             \"sound_timer\": 0,\n  \"halted\": false,\n  \
+             \"error\": \"Unknown opcode 5121 at 0x204\"\n}\n"
+        );
+        assert!(registers_json(&cpu, None).ends_with("  \"error\": null\n}\n"));
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(
+            json_string("\"a\\b\"\n\t\x01"),
+            "\"\\\"a\\\\b\\\"\\n\\t\\u0001\""
+        );
+    }
+}
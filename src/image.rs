@@ -0,0 +1,144 @@
+use crate::state::crc32;
+
+/// Largest payload of an uncompressed deflate block.
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+/// Binary PBM (P4) where every non-zero colour index is a black pixel.
+pub fn pbm(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut data = format!("P4\n{} {}\n", width, height).into_bytes();
+
+    for row in pixels.chunks(width) {
+        for bits in row.chunks(8) {
+            let byte = bits.iter().enumerate().fold(0u8, |byte, (i, &pixel)| {
+                byte | ((pixel != 0) as u8) << (7 - i)
+            });
+            data.push(byte);
+        }
+    }
+
+    data
+}
+
+/// Indexed-colour PNG with one palette entry per colour index. The image
+/// data is stored without compression, which keeps the encoder tiny.
+pub fn png(width: usize, height: usize, pixels: &[u8], palette: &[[u8; 3]]) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8-bit depth, indexed colour, default compression, filter and interlace
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+    chunk(&mut data, b"IHDR", &header);
+
+    chunk(&mut data, b"PLTE", &palette.concat());
+
+    let mut scanlines = vec![];
+    for row in pixels.chunks(width) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    chunk(&mut data, b"IDAT", &zlib_stored(&scanlines));
+
+    chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+fn chunk(data: &mut Vec<u8>, kind: &[u8; 4], payload: &[u8]) {
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+
+    let start = data.len();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(payload);
+
+    let checksum = crc32(&data[start..]);
+    data.extend_from_slice(&checksum.to_be_bytes());
+}
+
+fn zlib_stored(payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = payload.chunks(STORED_BLOCK_SIZE).peekable();
+
+    if blocks.peek().is_none() {
+        data.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        data.push(last as u8);
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+
+    data.extend_from_slice(&adler32(payload).to_be_bytes());
+    data
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_pbm_rows() {
+        let pixels = [
+            1, 0, 1, 0, 0, 0, 0, 0, 1, 0, //
+            0, 2, 0, 0, 0, 0, 0, 0, 0, 1,
+        ];
+
+        assert_eq!(pbm(10, 2, &pixels), b"P4\n10 2\n\xA0\x80\x40\x40");
+    }
+
+    #[test]
+    fn writes_a_valid_png() {
+        let palette = [[0, 0, 0], [0xFF, 0xFF, 0xFF]];
+        let data = png(2, 2, &[0, 1, 1, 0], &palette);
+
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+
+        // Walks the chunks, checking each length and CRC
+        let mut chunks = vec![];
+        let mut rest = &data[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, checksum) = rest[4..].split_at(4 + len);
+            assert_eq!(checksum[..4], crc32(body).to_be_bytes());
+            chunks.push((body[..4].to_vec(), body[4..].to_vec()));
+            rest = &checksum[4..];
+        }
+
+        let kinds = chunks.iter().map(|(kind, _)| &kind[..]).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, 3, 0, 0, 0]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 0xFF, 0xFF, 0xFF]);
+
+        let scanlines = [0, 0, 1, 0, 1, 0];
+        let mut idat = vec![0x78, 0x01, 1, 6, 0, !6, 0xFF];
+        idat.extend_from_slice(&scanlines);
+        idat.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+        assert_eq!(chunks[2].1, idat);
+    }
+
+    #[test]
+    fn splits_large_images_into_stored_blocks() {
+        let payload = vec![7; STORED_BLOCK_SIZE + 1];
+        let data = zlib_stored(&payload);
+
+        assert_eq!(data[2..7], [0, 0xFF, 0xFF, 0, 0]);
+        let second = 7 + STORED_BLOCK_SIZE;
+        assert_eq!(data[second..second + 5], [1, 1, 0, 0xFE, 0xFF]);
+        assert_eq!(data.len(), 2 + 2 * 5 + payload.len() + 4);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
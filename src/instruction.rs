@@ -0,0 +1,441 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn
+    Sys(u16),
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00Cn
+    Scd(u8),
+    /// 00FB
+    Scr,
+    /// 00FC
+    Scl,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Low,
+    /// 00FF
+    High,
+    /// 1nnn
+    Jp(u16),
+    /// 2nnn
+    Call(u16),
+    /// 3xnn
+    SeByte {
+        x: u8,
+        byte: u8,
+    },
+    /// 4xnn
+    SneByte {
+        x: u8,
+        byte: u8,
+    },
+    /// 5xy0
+    SeReg {
+        x: u8,
+        y: u8,
+    },
+    /// 5xy2
+    SaveRange {
+        x: u8,
+        y: u8,
+    },
+    /// 5xy3
+    LoadRange {
+        x: u8,
+        y: u8,
+    },
+    /// 6xnn
+    LdByte {
+        x: u8,
+        byte: u8,
+    },
+    /// 7xnn
+    AddByte {
+        x: u8,
+        byte: u8,
+    },
+    /// 8xy0
+    LdReg {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy1
+    Or {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy2
+    And {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy3
+    Xor {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy4
+    AddReg {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy5
+    Sub {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy6
+    Shr {
+        x: u8,
+        y: u8,
+    },
+    /// 8xy7
+    Subn {
+        x: u8,
+        y: u8,
+    },
+    /// 8xyE
+    Shl {
+        x: u8,
+        y: u8,
+    },
+    /// 9xy0
+    SneReg {
+        x: u8,
+        y: u8,
+    },
+    /// Annn
+    LdI(u16),
+    /// Bnnn
+    JpV0(u16),
+    /// Cxnn
+    Rnd {
+        x: u8,
+        byte: u8,
+    },
+    /// Dxyn
+    Drw {
+        x: u8,
+        y: u8,
+        n: u8,
+    },
+    /// Ex9E
+    Skp(u8),
+    /// ExA1
+    Sknp(u8),
+    /// F000 nnnn, the address lives in the word after the opcode
+    LdILong,
+    /// Fn01
+    Plane(u8),
+    /// F002
+    Audio,
+    /// Fx07
+    LdVxDt(u8),
+    /// Fx0A
+    LdVxK(u8),
+    /// Fx15
+    LdDtVx(u8),
+    /// Fx18
+    LdStVx(u8),
+    /// Fx1E
+    AddIVx(u8),
+    /// Fx29
+    LdFVx(u8),
+    /// Fx30
+    LdHfVx(u8),
+    /// Fx33
+    LdBVx(u8),
+    /// Fx3A
+    Pitch(u8),
+    /// Fx55
+    LdIVx(u8),
+    /// Fx65
+    LdVxI(u8),
+    /// Fx75
+    LdRVx(u8),
+    /// Fx85
+    LdVxR(u8),
+    Unknown(u16),
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let byte = (opcode & 0x00FF) as u8;
+    let addr = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Instruction::Cls,
+            0x00EE => Instruction::Ret,
+            0x00C0..=0x00CF => Instruction::Scd(n),
+            0x00FB => Instruction::Scr,
+            0x00FC => Instruction::Scl,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::Low,
+            0x00FF => Instruction::High,
+            _ => Instruction::Sys(addr),
+        },
+        0x1000 => Instruction::Jp(addr),
+        0x2000 => Instruction::Call(addr),
+        0x3000 => Instruction::SeByte { x, byte },
+        0x4000 => Instruction::SneByte { x, byte },
+        0x5000 => match n {
+            0x0 => Instruction::SeReg { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x6000 => Instruction::LdByte { x, byte },
+        0x7000 => Instruction::AddByte { x, byte },
+        0x8000 => match n {
+            0x0 => Instruction::LdReg { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::Shr { x, y },
+            0x7 => Instruction::Subn { x, y },
+            0xE => Instruction::Shl { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x9000 if n == 0 => Instruction::SneReg { x, y },
+        0xA000 => Instruction::LdI(addr),
+        0xB000 => Instruction::JpV0(addr),
+        0xC000 => Instruction::Rnd { x, byte },
+        0xD000 => Instruction::Drw { x, y, n },
+        0xE000 => match byte {
+            0x9E => Instruction::Skp(x),
+            0xA1 => Instruction::Sknp(x),
+            _ => Instruction::Unknown(opcode),
+        },
+        0xF000 => match opcode {
+            0xF000 => Instruction::LdILong,
+            0xF002 => Instruction::Audio,
+            _ => match byte {
+                0x01 => Instruction::Plane(x),
+                0x07 => Instruction::LdVxDt(x),
+                0x0A => Instruction::LdVxK(x),
+                0x15 => Instruction::LdDtVx(x),
+                0x18 => Instruction::LdStVx(x),
+                0x1E => Instruction::AddIVx(x),
+                0x29 => Instruction::LdFVx(x),
+                0x30 => Instruction::LdHfVx(x),
+                0x33 => Instruction::LdBVx(x),
+                0x3A => Instruction::Pitch(x),
+                0x55 => Instruction::LdIVx(x),
+                0x65 => Instruction::LdVxI(x),
+                0x75 => Instruction::LdRVx(x),
+                0x85 => Instruction::LdVxR(x),
+                _ => Instruction::Unknown(opcode),
+            },
+        },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+impl Instruction {
+    pub fn encode(&self) -> u16 {
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |base: u16, x: u8, byte: u8| base | (x as u16) << 8 | byte as u16;
+        let fx = |x: u8, low: u16| 0xF000 | (x as u16) << 8 | low;
+
+        match *self {
+            Instruction::Sys(addr) => addr & 0x0FFF,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Scd(n) => 0x00C0 | (n & 0xF) as u16,
+            Instruction::Scr => 0x00FB,
+            Instruction::Scl => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jp(addr) => 0x1000 | addr & 0x0FFF,
+            Instruction::Call(addr) => 0x2000 | addr & 0x0FFF,
+            Instruction::SeByte { x, byte } => xnn(0x3000, x, byte),
+            Instruction::SneByte { x, byte } => xnn(0x4000, x, byte),
+            Instruction::SeReg { x, y } => xy(0x5000, x, y),
+            Instruction::SaveRange { x, y } => xy(0x5002, x, y),
+            Instruction::LoadRange { x, y } => xy(0x5003, x, y),
+            Instruction::LdByte { x, byte } => xnn(0x6000, x, byte),
+            Instruction::AddByte { x, byte } => xnn(0x7000, x, byte),
+            Instruction::LdReg { x, y } => xy(0x8000, x, y),
+            Instruction::Or { x, y } => xy(0x8001, x, y),
+            Instruction::And { x, y } => xy(0x8002, x, y),
+            Instruction::Xor { x, y } => xy(0x8003, x, y),
+            Instruction::AddReg { x, y } => xy(0x8004, x, y),
+            Instruction::Sub { x, y } => xy(0x8005, x, y),
+            Instruction::Shr { x, y } => xy(0x8006, x, y),
+            Instruction::Subn { x, y } => xy(0x8007, x, y),
+            Instruction::Shl { x, y } => xy(0x800E, x, y),
+            Instruction::SneReg { x, y } => xy(0x9000, x, y),
+            Instruction::LdI(addr) => 0xA000 | addr & 0x0FFF,
+            Instruction::JpV0(addr) => 0xB000 | addr & 0x0FFF,
+            Instruction::Rnd { x, byte } => xnn(0xC000, x, byte),
+            Instruction::Drw { x, y, n } => xy(0xD000, x, y) | (n & 0xF) as u16,
+            Instruction::Skp(x) => xnn(0xE000, x, 0x9E),
+            Instruction::Sknp(x) => xnn(0xE000, x, 0xA1),
+            Instruction::LdILong => 0xF000,
+            Instruction::Plane(n) => fx(n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::LdVxDt(x) => fx(x, 0x07),
+            Instruction::LdVxK(x) => fx(x, 0x0A),
+            Instruction::LdDtVx(x) => fx(x, 0x15),
+            Instruction::LdStVx(x) => fx(x, 0x18),
+            Instruction::AddIVx(x) => fx(x, 0x1E),
+            Instruction::LdFVx(x) => fx(x, 0x29),
+            Instruction::LdHfVx(x) => fx(x, 0x30),
+            Instruction::LdBVx(x) => fx(x, 0x33),
+            Instruction::Pitch(x) => fx(x, 0x3A),
+            Instruction::LdIVx(x) => fx(x, 0x55),
+            Instruction::LdVxI(x) => fx(x, 0x65),
+            Instruction::LdRVx(x) => fx(x, 0x75),
+            Instruction::LdVxR(x) => fx(x, 0x85),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    /// Size in bytes, including the address word that follows `F000`.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SeByte { .. }
+                | Instruction::SneByte { .. }
+                | Instruction::SeReg { .. }
+                | Instruction::SneReg { .. }
+                | Instruction::Skp(_)
+                | Instruction::Sknp(_)
+        )
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SaveRange { .. }
+                | Instruction::LoadRange { .. }
+                | Instruction::LdILong
+                | Instruction::Plane(_)
+                | Instruction::Audio
+                | Instruction::Pitch(_)
+        )
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Sys(addr) => write!(f, "SYS {:#05X}", addr),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Scd(n) => write!(f, "SCD {}", n),
+            Instruction::Scr => write!(f, "SCR"),
+            Instruction::Scl => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::Jp(addr) => write!(f, "JP {:#05X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL {:#05X}", addr),
+            Instruction::SeByte { x, byte } => write!(f, "SE V{:X}, {:#04X}", x, byte),
+            Instruction::SneByte { x, byte } => write!(f, "SNE V{:X}, {:#04X}", x, byte),
+            Instruction::SeReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LdByte { x, byte } => write!(f, "LD V{:X}, {:#04X}", x, byte),
+            Instruction::AddByte { x, byte } => write!(f, "ADD V{:X}, {:#04X}", x, byte),
+            Instruction::LdReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(addr) => write!(f, "LD I, {:#05X}", addr),
+            Instruction::JpV0(addr) => write!(f, "JP V0, {:#05X}", addr),
+            Instruction::Rnd { x, byte } => write!(f, "RND V{:X}, {:#04X}", x, byte),
+            Instruction::Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LdILong => write!(f, "LD I, LONG"),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIVx(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdFVx(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LdHfVx(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdBVx(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LdVxR(x) => write!(f, "LD V{:X}, R", x),
+            Instruction::Unknown(opcode) => write!(f, "DW {:#06X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_every_decoded_opcode_back() {
+        for opcode in 0x0000..=0xFFFF {
+            assert_eq!(decode(opcode).encode(), opcode, "{:#06X}", opcode);
+        }
+    }
+
+    #[test]
+    fn displays_instructions() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x00C4, "SCD 4"),
+            (0x0123, "SYS 0x123"),
+            (0x1ABC, "JP 0xABC"),
+            (0x3A0F, "SE VA, 0x0F"),
+            (0x5122, "SAVE V1, V2"),
+            (0x8E36, "SHR VE, V3"),
+            (0xA200, "LD I, 0x200"),
+            (0xB300, "JP V0, 0x300"),
+            (0xD12F, "DRW V1, V2, 15"),
+            (0xEBA1, "SKNP VB"),
+            (0xF000, "LD I, LONG"),
+            (0xF201, "PLANE 2"),
+            (0xF465, "LD V4, [I]"),
+            (0xFF85, "LD VF, R"),
+            (0x5121, "DW 0x5121"),
+            (0xE000, "DW 0xE000"),
+        ];
+
+        for (opcode, text) in cases {
+            assert_eq!(decode(opcode).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn long_load_spans_two_words() {
+        assert_eq!(decode(0xF000).size(), 4);
+        assert_eq!(decode(0xF001).size(), 2);
+        assert!(decode(0xF000).is_xo_chip());
+        assert!(!decode(0x00E0).is_xo_chip());
+    }
+}
@@ -0,0 +1,62 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP emulator core.
+//!
+//! The core has no frontend of its own: create a [`Chip8`], load a ROM, call
+//! [`Chip8::tick`] once per frame (60 times a second) and read the display
+//! back with [`Chip8::framebuffer`]. Keys are passed in as a bit mask of
+//! [`KeyMask`] values with [`Chip8::set_keypad`].
+//!
+//! To run in real time, implement the traits in [`frontend`] and hand them
+//! to [`frontend::run`], which keeps the machine on its own thread. The
+//! terminal debugger and GDB stub it can drive are behind the `debugger`
+//! feature, which is on by default.
+//!
+//! ```
+//! use chip8::{Chip8, Config};
+//!
+//! let mut cpu = Chip8::with_config(&Config {
+//!     seed: Some(1),
+//!     ..Config::default()
+//! });
+//! // 0x200: LD V0, 0x2A; JP 0x202
+//! cpu.load_rom(&[0x60, 0x2A, 0x12, 0x02]).unwrap();
+//! cpu.tick().unwrap();
+//!
+//! assert_eq!(cpu.registers().v[0], 0x2A);
+//! ```
+
+pub mod asm;
+pub mod chip8;
+pub mod config;
+pub mod coverage;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod font;
+pub mod frontend;
+#[cfg(feature = "debugger")]
+pub mod gdb;
+pub mod headless;
+pub mod image;
+pub mod instruction;
+pub mod movie;
+pub mod profiler;
+pub mod quirks;
+mod rewind;
+pub mod rng;
+pub mod scheduler;
+mod state;
+pub mod symbols;
+pub mod trace;
+pub mod watch;
+
+pub use chip8::{
+    Chip8, KeyMask, Registers, DEFAULT_CYCLES_PER_FRAME, FRAME_RATE, HIRES_HEIGHT, HIRES_WIDTH,
+    LORES_HEIGHT, LORES_WIDTH, PLANES,
+};
+pub use config::Config;
+pub use error::{Chip8Error, StateError};
+pub use font::{BigFontSet, FontSet};
+pub use instruction::Instruction;
+pub use quirks::{LoadStoreIncrement, Quirks};
+pub use rng::RandomMode;
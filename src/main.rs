@@ -1,60 +1,285 @@
+use std::io::BufWriter;
 use std::process::exit;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
 
 use args::Args;
-use chip8::{Chip8, KeyMask};
-use sdl2::audio::AudioCallback;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::Sdl;
+use chip8::coverage::Coverage;
+#[cfg(feature = "debugger")]
+use chip8::debugger::{self, Debugger};
+use chip8::disasm::Disassembly;
+use chip8::frontend::RunOptions;
+#[cfg(feature = "debugger")]
+use chip8::gdb::GdbStub;
+use chip8::headless::{self, InputScript};
+use chip8::movie::{Movie, MoviePlayer};
+use chip8::profiler::Profiler;
+use chip8::trace::{self, Tracer};
+use chip8::{asm, image};
+use chip8::{BigFontSet, Chip8, Config, FontSet, Quirks, RandomMode, FRAME_RATE};
+#[cfg(feature = "sdl")]
+use sdl::SdlOptions;
 
 mod args;
-mod chip8;
+#[cfg(feature = "sdl")]
+mod audio;
+#[cfg(feature = "sdl")]
+mod sdl;
+mod terminal;
 
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
-
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
-}
-
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
-    }
-}
-
-fn to_color(hex: String) -> Color {
+fn to_color(hex: String) -> [u8; 3] {
     let hex = hex.trim_start_matches('#');
     let r = u8::from_str_radix(&hex[0..2], 16).unwrap();
     let g = u8::from_str_radix(&hex[2..4], 16).unwrap();
     let b = u8::from_str_radix(&hex[4..6], 16).unwrap();
-    Color::RGB(r, g, b)
+    [r, g, b]
 }
 
 fn help() {
     println!("Usage: chip8 <rom> [options]");
+    println!("       chip8 run <rom> --headless --frames=<n> [options]");
+    println!("       chip8 disasm <rom> [--octo | --source]");
+    println!("       chip8 asm <source> -o <rom> [--symbols=<file>]");
+    println!("       chip8 coverage <rom> <map>...");
     println!();
     println!("Options:");
     println!("  --help                Show this help message");
     println!("  --background=<color>  Background color (default: #000000)");
     println!("  --foreground=<color>  Foreground color (default: #FFFFFF)");
+    println!("  --color2=<color>      XO-CHIP second plane color (default: #FF6600)");
+    println!("  --color3=<color>      XO-CHIP overlapping planes color (default: #662200)");
     println!("  --clock=<hz>          Clock speed (default: 500)");
+    println!("  --ipf=<n>             Instructions per frame, overrides --clock");
     println!("  --audio-freq=<hz>     Audio frequency (default: 880)");
     println!("  --volume=<volume>     Audio volume (default: 0.25)");
+    println!(
+        "  --font=<name>         Font set: chip8, vip, dream6800, eti660, octo (default: chip8)"
+    );
+    println!("  --big-font=<name>     Big font set for Fx30: schip, octo (default: schip)");
+    println!("  --xo-chip             Enable XO-CHIP extensions");
+    println!("  --quirks=<profile>    Quirks: vip, chip48, schip, xochip, none, or a list of quirks (default: none, xochip with --xo-chip)");
+    println!("  --seed=<n>            Seed for CXNN random numbers (default: random)");
+    println!("  --random=<mode>       Random generator: xorshift, vip (default: xorshift)");
+    println!("  --rewind=<frames>     Frames kept for rewinding, 0 to disable (default: 600)");
+    println!("  --record=<movie>      Record keypad input to a movie file");
+    println!("  --play=<movie>        Play back a recorded movie");
+    println!("  --terminal[=<mode>]   Draw on the terminal with halfblock or braille characters (default: halfblock)");
+    println!("  --debug               Start paused with a debugger prompt on the terminal");
+    println!("  --symbols=<file>      Show label names from a symbol file in the debugger");
+    println!("  --gdb=<port>          Wait for a GDB remote protocol client on a local port");
+    println!("  --trace=<file>        Log every executed instruction to a file");
+    println!("  --trace-range=<a-b>   Only log instructions between two hex addresses");
+    println!("  --trace-last=<n>      Only keep the last n instructions, written on error");
+    println!("  --profile             Print the hottest addresses and subroutines on exit");
+    println!("  --profile-folded=<file>  Write call stacks for flame graph tools on exit");
+    println!("  --coverage=<map>      Merge the bytes used by this run into a coverage map");
+    println!();
+    println!("Headless options:");
+    println!("  --headless            Run without a window or audio");
+    println!("  --frames=<n>          Frames to run (default: 600)");
+    println!(
+        "  --input=<script>      Key presses to send, one '<frame> press|release <key>' per line"
+    );
+    println!("  --screenshot=<file>   Write the final display as .png or .pbm");
+    println!("  --registers=<file>    Write the final registers as JSON");
+    println!();
+    println!("Hotkeys:");
+    println!("  Shift+F1..F9          Save state to slot 1..9");
+    println!("  F1..F9                Load state from slot 1..9");
+    println!("  Backspace (hold)      Rewind");
+    println!();
+    println!("Disassembler options:");
+    println!("  --octo                Print Octo source that reassembles to the same ROM");
+    println!(
+        "  --source              Print source that the asm command reassembles to the same ROM"
+    );
+    println!();
+    println!("Assembler options:");
+    println!("  -o <rom>              Output ROM file (default: <source> with .ch8 extension)");
+    println!("  --symbols=<file>      Write label addresses to a symbol file");
+}
+
+fn asm(args: &Args) {
+    let source = args.positional(1).unwrap_or_else(|| {
+        println!("Usage: chip8 asm <source> -o <rom>");
+        exit(1);
+    });
+    let output = args.option("o").unwrap_or_else(|| {
+        std::path::Path::new(source)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+
+    let assembly = asm::assemble(std::path::Path::new(source)).unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(1);
+    });
+
+    std::fs::write(&output, &assembly.rom).unwrap_or_else(|e| {
+        println!("Could not write {}: {}", output, e);
+        exit(1);
+    });
+
+    if let Some(symbols) = args.option("symbols") {
+        std::fs::write(&symbols, assembly.symbols.to_string()).unwrap_or_else(|e| {
+            println!("Could not write {}: {}", symbols, e);
+            exit(1);
+        });
+    }
+}
+
+fn read_coverage(path: &str) -> Coverage {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", path, e);
+        exit(1);
+    });
+
+    text.parse().unwrap_or_else(|e| {
+        println!("{}: {}", path, e);
+        exit(1);
+    })
+}
+
+fn coverage(args: &Args) {
+    let (Some(rom), Some(_)) = (args.positional(1), args.positional(2)) else {
+        println!("Usage: chip8 coverage <rom> <map>...");
+        exit(1);
+    };
+    let rom = std::fs::read(rom).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", rom, e);
+        exit(1);
+    });
+
+    let mut coverage = Coverage::default();
+
+    for path in (2..).map_while(|i| args.positional(i)) {
+        coverage.merge(&read_coverage(path));
+    }
+
+    print!("{}", coverage.report(&rom));
+}
+
+fn disasm(args: &Args) {
+    let rom = args.positional(1).unwrap_or_else(|| {
+        println!("Usage: chip8 disasm <rom> [--octo | --source]");
+        exit(1);
+    });
+    let rom = std::fs::read(rom).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", rom, e);
+        exit(1);
+    });
+    let disassembly = Disassembly::new(&rom);
+
+    if args.has_option("octo") {
+        print!("{}", disassembly.octo());
+    } else if args.has_option("source") {
+        print!("{}", disassembly.source());
+    } else {
+        print!("{}", disassembly.listing());
+    }
+}
+
+fn run_options(args: &Args, player: Option<MoviePlayer>, rom_path: String) -> RunOptions {
+    // O terminal e o depurador leem a mesma entrada
+    if args.has_option("terminal") && args.has_option("debug") {
+        println!("--debug and --terminal can't be used together");
+        exit(1);
+    }
+
+    #[cfg(feature = "debugger")]
+    let (debugger, gdb) = debug_tools(args);
+
+    #[cfg(not(feature = "debugger"))]
+    if args.has_option("debug") || args.has_option("gdb") {
+        println!("This build has no debugger");
+        exit(1);
+    }
+
+    RunOptions {
+        player,
+        rom_path,
+        #[cfg(feature = "debugger")]
+        debugger,
+        #[cfg(feature = "debugger")]
+        gdb,
+    }
+}
+
+/// The debugger or GDB stub asked for on the command line.
+#[cfg(feature = "debugger")]
+fn debug_tools(args: &Args) -> (Option<Debugger>, Option<GdbStub>) {
+    if args.has_option("gdb") && args.has_option("debug") {
+        println!("--debug and --gdb can't be used together");
+        exit(1);
+    }
+
+    let gdb = args.option("gdb").map(|port| {
+        let port = port.parse::<u16>().unwrap_or_else(|_| {
+            println!("Invalid GDB port");
+            exit(1);
+        });
+        let stub = GdbStub::bind(port).unwrap_or_else(|e| {
+            println!("Could not listen on port {}: {}", port, e);
+            exit(1);
+        });
+
+        println!("Waiting for GDB on {}", stub.local_addr().unwrap());
+        stub
+    });
+
+    let debugger = args.has_option("debug").then(|| {
+        let mut debugger = Debugger::new(debugger::stdin_lines(), std::io::stdout());
+
+        if let Some(path) = args.option("symbols") {
+            let symbols = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| text.parse())
+                .unwrap_or_else(|e| {
+                    println!("Could not load symbols from {}: {}", path, e);
+                    exit(1);
+                });
+            debugger.set_symbols(symbols);
+        }
+
+        debugger
+    });
+
+    (debugger, gdb)
+}
+
+/// Runs the ROM in a window until it is closed.
+#[cfg(feature = "sdl")]
+fn window(
+    chip8: Chip8,
+    options: RunOptions,
+    args: &Args,
+    palette: [[u8; 3]; 4],
+    xo_chip: bool,
+) -> Chip8 {
+    let audio_freq = args
+        .option("audio-freq")
+        .unwrap_or("880".to_string())
+        .parse::<f32>()
+        .unwrap_or_else(|_| {
+            println!("Invalid audio frequency value");
+            exit(1);
+        });
+
+    sdl::run(
+        chip8,
+        options,
+        SdlOptions {
+            palette,
+            audio_freq,
+            xo_chip,
+        },
+    )
+}
+
+#[cfg(not(feature = "sdl"))]
+fn window(_: Chip8, _: RunOptions, _: &Args, _: [[u8; 3]; 4], _: bool) -> Chip8 {
+    println!("This build has no window, run with --terminal or --headless");
+    exit(1);
 }
 
 fn main() {
@@ -64,15 +289,38 @@ fn main() {
         return help();
     }
 
-    let rom = args
+    if args
+        .positional(0)
+        .is_some_and(|command| command == "disasm")
+    {
+        return disasm(&args);
+    }
+
+    if args.positional(0).is_some_and(|command| command == "asm") {
+        return asm(&args);
+    }
+
+    if args
         .positional(0)
+        .is_some_and(|command| command == "coverage")
+    {
+        return coverage(&args);
+    }
+
+    let run = args.positional(0).is_some_and(|command| command == "run");
+    let headless = run && args.has_option("headless");
+    let rom = args
+        .positional(if run { 1 } else { 0 })
         .unwrap_or_else(|| {
-            println!("Usage: chip8 <rom> <background>");
+            println!("Usage: chip8 run <rom> [options]");
             exit(1);
         })
         .clone();
     let background = to_color(args.option("background").unwrap_or("#000000".to_string()));
     let foreground = to_color(args.option("foreground").unwrap_or("#FFFFFF".to_string()));
+    let color2 = to_color(args.option("color2").unwrap_or("#FF6600".to_string()));
+    let color3 = to_color(args.option("color3").unwrap_or("#662200".to_string()));
+    let xo_chip = args.has_option("xo-chip");
     let clock = args
         .option("clock")
         .unwrap_or("500".to_string())
@@ -81,177 +329,284 @@ fn main() {
             println!("Invalid clock value");
             exit(1);
         });
-    let audio_freq = args
-        .option("audio-freq")
-        .unwrap_or("880".to_string())
-        .parse::<f32>()
+    let cycles_per_frame = args
+        .option("ipf")
+        .map(|ipf| ipf.parse::<u32>())
+        .unwrap_or(Ok((clock + FRAME_RATE / 2) / FRAME_RATE))
         .unwrap_or_else(|_| {
-            println!("Invalid audio frequency value");
+            println!("Invalid instructions per frame value");
             exit(1);
         });
 
-    let cpu = Arc::new(Mutex::new(Chip8::new()));
+    let font = args
+        .option("font")
+        .unwrap_or("chip8".to_string())
+        .parse::<FontSet>()
+        .unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        });
+    let big_font = args
+        .option("big-font")
+        .unwrap_or("schip".to_string())
+        .parse::<BigFontSet>()
+        .unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        });
+    // Sem --quirks as ROMs rodam como antes dos quirks existirem
+    let quirks = match args.option("quirks") {
+        Some(quirks) => quirks.parse::<Quirks>().unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        }),
+        None if xo_chip => Quirks::XO_CHIP,
+        None => Quirks::default(),
+    };
+
+    let seed = args
+        .option("seed")
+        .map(|seed| seed.parse::<u64>())
+        .unwrap_or_else(|| Ok(rand::random()))
+        .unwrap_or_else(|_| {
+            println!("Invalid seed value");
+            exit(1);
+        });
+    let random = args
+        .option("random")
+        .unwrap_or("xorshift".to_string())
+        .parse::<RandomMode>()
+        .unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        });
+    let rewind = args
+        .option("rewind")
+        .unwrap_or("600".to_string())
+        .parse::<usize>()
+        .unwrap_or_else(|_| {
+            println!("Invalid rewind value");
+            exit(1);
+        });
+    let record = args.option("record");
 
-    let t1_cpu = cpu.clone();
+    // Sem --trace não há onde escrever o log
+    for option in ["trace-range", "trace-last"] {
+        if args.has_option(option) && !args.has_option("trace") {
+            println!("--{} needs --trace=<file>", option);
+            exit(1);
+        }
+    }
 
-    let t1 = std::thread::spawn(move || {
-        let rom = std::fs::read(rom).unwrap();
-        t1_cpu.lock().unwrap().load_rom(rom.as_slice());
+    let trace_range = args.option("trace-range").map(|range| {
+        trace::parse_range(&range).unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        })
+    });
+    let trace_last = args
+        .option("trace-last")
+        .unwrap_or("0".to_string())
+        .parse::<usize>()
+        .unwrap_or_else(|_| {
+            println!("Invalid trace length");
+            exit(1);
+        });
+    let movie = args.option("play").map(|path| {
+        std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|movie| movie.parse::<Movie>())
+            .unwrap_or_else(|e| {
+                println!("Could not read {}: {}", path, e);
+                exit(1);
+            })
+    });
 
-        loop {
-            let mut cpu = t1_cpu.lock().unwrap();
-            cpu.tick();
-            if cpu.halted {
-                break;
-            }
-            drop(cpu);
-            std::thread::sleep(Duration::from_secs(1) / clock);
-        }
+    // A movie only replays correctly with the settings it was recorded with
+    let config = match &movie {
+        Some(movie) => movie.config(),
+        None => Config {
+            font,
+            big_font,
+            cycles_per_frame,
+            quirks,
+            xo_chip,
+            random,
+            seed: Some(seed),
+            rewind: if headless { 0 } else { rewind },
+        },
+    };
+    let (seed, xo_chip) = (config.seed.unwrap_or_default(), config.xo_chip);
+    let mut chip8 = Chip8::with_config(&config);
+
+    let rom_path = rom.clone();
+    let rom = std::fs::read(&rom).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", rom, e);
+        exit(1);
     });
 
-    let t2_cpu = cpu.clone();
-    let t2 = std::thread::spawn(move || {
-        // Inicializa SDL
-        let sdl_context: Sdl = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let audio_subsystem = sdl_context.audio().unwrap();
-
-        // Cria uma janela
-        let window = video_subsystem
-            .window("Emulador Chip-8", 640, 320)
-            .position_centered()
-            .resizable()
-            .build()
-            .map_err(|e| e.to_string())
-            .unwrap();
+    if let Err(e) = chip8.load_rom(rom.as_slice()) {
+        println!("{}", e);
+        exit(1);
+    }
 
-        let mut canvas = window
-            .into_canvas()
-            .build()
-            .map_err(|e| e.to_string())
-            .unwrap();
-
-        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
-
-        // Loop de evento
-        let mut event_pump = sdl_context.event_pump().unwrap();
-
-        let desired_spec = sdl2::audio::AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1),
-            samples: None,
-        };
-        let device = audio_subsystem
-            .open_playback(None, &desired_spec, |spec| {
-                // initialize the audio callback
-                SquareWave {
-                    phase_inc: audio_freq / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25,
-                }
+    if movie
+        .as_ref()
+        .is_some_and(|movie| movie.rom_hash != chip8.rom_hash())
+    {
+        println!("The movie was recorded with a different ROM");
+        exit(1);
+    }
+
+    if record.is_some() {
+        chip8.start_recording(Movie::new(chip8.rom_hash(), seed, &config));
+    }
+
+    if let Some(path) = args.option("trace") {
+        let file = std::fs::File::create(&path).unwrap_or_else(|e| {
+            println!("Could not write {}: {}", path, e);
+            exit(1);
+        });
+        let output = Arc::new(Mutex::new(BufWriter::new(file)));
+
+        chip8.start_tracing(Tracer::new(output, trace_range, trace_last));
+    }
+
+    let profile_folded = args.option("profile-folded");
+
+    if args.has_option("profile") || profile_folded.is_some() {
+        chip8.start_profiling(Profiler::default());
+    }
+
+    let coverage = args.option("coverage");
+
+    if let Some(path) = &coverage {
+        if std::path::Path::new(path).exists() {
+            chip8.start_coverage(read_coverage(path));
+        } else {
+            chip8.start_coverage(Coverage::default());
+        }
+    }
+
+    let player = movie.map(MoviePlayer::new);
+    let mut failed = false;
+
+    let mut chip8 = if headless {
+        let frames = args
+            .option("frames")
+            .unwrap_or("600".to_string())
+            .parse::<u64>()
+            .unwrap_or_else(|_| {
+                println!("Invalid frames value");
+                exit(1);
+            });
+        let script = args
+            .option("input")
+            .map(|path| {
+                std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|script| script.parse::<InputScript>())
+                    .unwrap_or_else(|e| {
+                        println!("Could not read {}: {}", path, e);
+                        exit(1);
+                    })
             })
-            .unwrap();
-
-        // Limpa a tela
-        canvas.set_draw_color(background);
-        canvas.clear();
-
-        'running: loop {
-            // Update Audio
-            if t2_cpu.lock().unwrap().sound_timer > 0
-                && device.status() != sdl2::audio::AudioStatus::Playing
-            {
-                device.resume();
-            } else if t2_cpu.lock().unwrap().sound_timer == 0
-                && device.status() == sdl2::audio::AudioStatus::Playing
-            {
-                device.pause();
+            .unwrap_or_default();
+
+        let result = headless::run(&mut chip8, frames, &script, player);
+
+        if let Err(e) = &result {
+            println!("{}", e);
+            failed = true;
+        }
+
+        if let Some(path) = args.option("screenshot") {
+            let width = chip8.display_width();
+            let height = chip8.display_height();
+            let pixels = chip8.framebuffer();
+            let image = if path.to_lowercase().ends_with(".png") {
+                image::png(
+                    width,
+                    height,
+                    &pixels,
+                    &[background, foreground, color2, color3],
+                )
+            } else {
+                image::pbm(width, height, &pixels)
+            };
+
+            if let Err(e) = std::fs::write(&path, image) {
+                println!("Could not write {}: {}", path, e);
+                exit(1);
             }
+        }
+
+        if let Some(path) = args.option("registers") {
+            let json = headless::registers_json(&chip8, result.as_ref().err());
 
-            for event in event_pump.poll_iter() {
-                match event {
-                    sdl2::event::Event::Quit { .. } => {
-                        cpu.lock().unwrap().halted = true;
-                        break 'running;
-                    }
-                    sdl2::event::Event::KeyDown {
-                        keycode: Some(key), ..
-                    } => t2_cpu.lock().unwrap().on_key_down(get_key_mask(key)),
-                    sdl2::event::Event::KeyUp {
-                        keycode: Some(key), ..
-                    } => t2_cpu.lock().unwrap().on_key_up(get_key_mask(key)),
-                    // sdl2::event::Event::MouseButtonDown { window_id, .. }
-                    // | sdl2::event::Event::MouseButtonUp { window_id, .. }
-                    //     if window_id == keypad.window_id =>
-                    // {
-                    //     keypad.handle_event(event);
-                    //     keypad.draw();
-                    // }
-                    _ => {}
-                }
+            if let Err(e) = std::fs::write(&path, json) {
+                println!("Could not write {}: {}", path, e);
+                exit(1);
             }
+        }
+
+        chip8
+    } else {
+        let palette = [background, foreground, color2, color3];
+        let options = run_options(&args, player, rom_path);
+
+        if args.has_option("terminal") {
+            let mode = args
+                .option("terminal")
+                .unwrap_or("halfblock".to_string())
+                .parse::<terminal::Mode>()
+                .unwrap_or_else(|e| {
+                    println!("{}", e);
+                    exit(1);
+                });
+
+            terminal::run(chip8, options, palette, mode)
+        } else {
+            window(chip8, options, &args, palette, xo_chip)
+        }
+    };
+
+    if let Some(tracer) = chip8.stop_tracing() {
+        tracer.flush();
+    }
+
+    if let Some(profiler) = chip8.stop_profiling() {
+        if args.has_option("profile") {
+            print!("{}", profiler.report(chip8.memory()));
+        }
 
-            let buffer = t2_cpu.lock().unwrap().display;
-
-            let (window_width, window_height) = canvas.output_size().unwrap();
-            let scale_x = window_width / WIDTH;
-            let scale_y = window_height / HEIGHT;
-            let scale = scale_x.min(scale_y);
-
-            // Desenha o buffer na tela
-            for y in 0..HEIGHT {
-                for x in 0..WIDTH {
-                    let byte = buffer[(y * WIDTH + x) as usize / 8];
-                    let bit = byte >> (7 - x % 8) & 1;
-
-                    let color = if bit == 0 {
-                        // Simula o efeito de fade dos monitores CRT
-                        Color::RGBA(background.r, background.g, background.b, 48)
-                    } else {
-                        foreground
-                    };
-
-                    let rect = Rect::new(
-                        x as i32 * scale as i32,
-                        y as i32 * scale as i32,
-                        scale,
-                        scale,
-                    );
-
-                    canvas.set_draw_color(color);
-                    canvas.fill_rect(rect).unwrap();
-                }
+        if let Some(path) = profile_folded {
+            if let Err(e) = std::fs::write(&path, profiler.folded()) {
+                println!("Could not write {}: {}", path, e);
+                exit(1);
             }
+        }
+    }
 
-            std::thread::sleep(Duration::from_secs(1) / 60);
+    if let Some(path) = coverage {
+        let coverage = chip8.stop_coverage().unwrap();
 
-            // Atualiza o canvas
-            canvas.present();
+        if let Err(e) = std::fs::write(&path, coverage.to_string()) {
+            println!("Could not write {}: {}", path, e);
+            exit(1);
         }
-    });
+    }
 
-    t1.join().unwrap();
-    t2.join().unwrap();
-}
+    if let Some(path) = record {
+        let movie = chip8.stop_recording().unwrap();
+
+        if let Err(e) = std::fs::write(&path, movie.to_string()) {
+            println!("Could not write {}: {}", path, e);
+            exit(1);
+        }
+    }
 
-fn get_key_mask(key: sdl2::keyboard::Keycode) -> u16 {
-    match key {
-        sdl2::keyboard::Keycode::Num0 => KeyMask::Key0 as u16,
-        sdl2::keyboard::Keycode::Num1 => KeyMask::Key1 as u16,
-        sdl2::keyboard::Keycode::Num2 => KeyMask::Key2 as u16,
-        sdl2::keyboard::Keycode::Num3 => KeyMask::Key3 as u16,
-        sdl2::keyboard::Keycode::Num4 => KeyMask::Key4 as u16,
-        sdl2::keyboard::Keycode::Num5 => KeyMask::Key5 as u16,
-        sdl2::keyboard::Keycode::Num6 => KeyMask::Key6 as u16,
-        sdl2::keyboard::Keycode::Num7 => KeyMask::Key7 as u16,
-        sdl2::keyboard::Keycode::Num8 => KeyMask::Key8 as u16,
-        sdl2::keyboard::Keycode::Num9 => KeyMask::Key9 as u16,
-        sdl2::keyboard::Keycode::A => KeyMask::KeyA as u16,
-        sdl2::keyboard::Keycode::B => KeyMask::KeyB as u16,
-        sdl2::keyboard::Keycode::C => KeyMask::KeyC as u16,
-        sdl2::keyboard::Keycode::D => KeyMask::KeyD as u16,
-        sdl2::keyboard::Keycode::E => KeyMask::KeyE as u16,
-        sdl2::keyboard::Keycode::F => KeyMask::KeyF as u16,
-        _ => 0,
+    if failed {
+        exit(1);
     }
 }
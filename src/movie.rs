@@ -0,0 +1,343 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip8::Chip8;
+use crate::config::Config;
+use crate::font::{BigFontSet, FontSet};
+use crate::quirks::Quirks;
+use crate::rng::RandomMode;
+
+const HEADER: &str = "chip8-movie 1";
+
+/// Frames between two state hashes embedded in a movie.
+pub const HASH_INTERVAL: u64 = 30;
+
+/// Keypad changes with the frame they happened on, plus everything needed
+/// to start the machine the same way again. Stored as text, one entry per
+/// line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub random: RandomMode,
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+    pub xo_chip: bool,
+    pub font: FontSet,
+    pub big_font: BigFontSet,
+    inputs: Vec<(u64, u16)>,
+    hashes: Vec<(u64, u64)>,
+}
+
+impl Movie {
+    /// Starts an empty movie for a machine built from `config` and seeded
+    /// with `seed`.
+    pub fn new(rom_hash: u64, seed: u64, config: &Config) -> Self {
+        Movie {
+            rom_hash,
+            seed,
+            random: config.random,
+            quirks: config.quirks,
+            cycles_per_frame: config.cycles_per_frame,
+            xo_chip: config.xo_chip,
+            font: config.font,
+            big_font: config.big_font,
+            inputs: vec![],
+            hashes: vec![],
+        }
+    }
+
+    /// The settings the movie was recorded with, without rewinding, which
+    /// would let the player step outside the recording.
+    pub fn config(&self) -> Config {
+        Config {
+            font: self.font,
+            big_font: self.big_font,
+            cycles_per_frame: self.cycles_per_frame,
+            quirks: self.quirks,
+            xo_chip: self.xo_chip,
+            random: self.random,
+            seed: Some(self.seed),
+            rewind: 0,
+        }
+    }
+
+    pub fn record_input(&mut self, frame: u64, keypad: u16) {
+        self.inputs.push((frame, keypad));
+    }
+
+    pub fn record_hash(&mut self, frame: u64, hash: u64) {
+        self.hashes.push((frame, hash));
+    }
+
+    /// Forgets everything recorded after `frame`, used when the machine
+    /// goes back in time while recording.
+    pub fn truncate(&mut self, frame: u64) {
+        self.inputs.retain(|&(f, _)| f <= frame);
+        self.hashes.retain(|&(f, _)| f <= frame);
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "random {}", self.random)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "ipf {}", self.cycles_per_frame)?;
+        writeln!(f, "xo-chip {}", self.xo_chip)?;
+        writeln!(f, "font {}", self.font)?;
+        writeln!(f, "big-font {}", self.big_font)?;
+
+        let mut inputs = self.inputs.iter().peekable();
+        let mut hashes = self.hashes.iter().peekable();
+
+        // Entries are merged so the file reads in frame order
+        loop {
+            match (inputs.peek(), hashes.peek()) {
+                (Some((frame, keypad)), hash) if hash.is_none_or(|(h, _)| frame <= h) => {
+                    writeln!(f, "input {} {:#06x}", frame, keypad)?;
+                    inputs.next();
+                }
+                (_, Some((frame, hash))) => {
+                    writeln!(f, "hash {} {:016x}", frame, hash)?;
+                    hashes.next();
+                }
+                (None, None) => return Ok(()),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err("Not a movie file".to_string());
+        }
+
+        let mut movie = Movie::new(
+            0,
+            0,
+            &Config {
+                cycles_per_frame: 0,
+                ..Config::default()
+            },
+        );
+
+        for (index, line) in lines {
+            let error = |message: &str| format!("Line {}: {}", index + 1, message);
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let number = |i: usize, radix: u32| {
+                let field = fields.get(i).ok_or_else(|| error("missing value"))?;
+                let digits = field.trim_start_matches("0x");
+                u64::from_str_radix(digits, radix).map_err(|_| error("invalid number"))
+            };
+
+            match fields.first() {
+                None => {}
+                Some(&"rom") => movie.rom_hash = number(1, 16)?,
+                Some(&"seed") => movie.seed = number(1, 10)?,
+                Some(&"random") => {
+                    movie.random = fields
+                        .get(1)
+                        .unwrap_or(&"")
+                        .parse()
+                        .map_err(|e: String| error(&e))?
+                }
+                Some(&"quirks") => {
+                    movie.quirks = fields
+                        .get(1)
+                        .unwrap_or(&"")
+                        .parse()
+                        .map_err(|e: String| error(&e))?
+                }
+                Some(&"ipf") => movie.cycles_per_frame = number(1, 10)? as u32,
+                Some(&"xo-chip") => movie.xo_chip = fields.get(1) == Some(&"true"),
+                Some(&"font") => {
+                    movie.font = fields
+                        .get(1)
+                        .unwrap_or(&"")
+                        .parse()
+                        .map_err(|e: String| error(&e))?
+                }
+                Some(&"big-font") => {
+                    movie.big_font = fields
+                        .get(1)
+                        .unwrap_or(&"")
+                        .parse()
+                        .map_err(|e: String| error(&e))?
+                }
+                Some(&"input") => movie.inputs.push((number(1, 10)?, number(2, 16)? as u16)),
+                Some(&"hash") => movie.hashes.push((number(1, 10)?, number(2, 16)?)),
+                Some(other) => return Err(error(&format!("unknown entry '{}'", other))),
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u64,
+    pub last_match: Option<u64>,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Movie desynced at frame {}", self.frame)?;
+
+        match self.last_match {
+            Some(frame) => write!(f, " (last in sync at frame {})", frame),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Feeds a movie's input into a machine and checks its embedded hashes.
+pub struct MoviePlayer {
+    movie: Movie,
+    next_input: usize,
+    next_hash: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer {
+            movie,
+            next_input: 0,
+            next_hash: 0,
+        }
+    }
+
+    /// Applies the keypad changes recorded for the frame about to run.
+    pub fn before_tick(&mut self, cpu: &mut Chip8) {
+        while let Some(&(frame, keypad)) = self.movie.inputs.get(self.next_input) {
+            if frame > cpu.frame() {
+                break;
+            }
+
+            cpu.set_keypad(keypad);
+            self.next_input += 1;
+        }
+    }
+
+    /// Compares the machine against the hash recorded for the frame that
+    /// just ran, if there is one.
+    pub fn after_tick(&mut self, cpu: &Chip8) -> Result<(), Desync> {
+        while let Some(&(frame, hash)) = self.movie.hashes.get(self.next_hash) {
+            if frame > cpu.frame() {
+                break;
+            }
+
+            self.next_hash += 1;
+
+            if frame == cpu.frame() && hash != cpu.state_hash() {
+                return Err(Desync {
+                    frame,
+                    last_match: self
+                        .next_hash
+                        .checked_sub(2)
+                        .map(|i| self.movie.hashes[i].0),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next_input == self.movie.inputs.len() && self.next_hash == self.movie.hashes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: RND V0, 0xFF; SKP V1; JP 0x200; ADD V2, 1; JP 0x200
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00];
+
+    const CONFIG: Config = Config {
+        font: FontSet::Octo,
+        big_font: BigFontSet::Octo,
+        cycles_per_frame: 10,
+        quirks: Quirks::SUPER_CHIP,
+        xo_chip: false,
+        random: RandomMode::CosmacVip,
+        seed: Some(7),
+        rewind: 0,
+    };
+
+    /// Records 90 frames with key 0 held from `press` to `release`.
+    fn record(press: u64, release: u64) -> (Movie, u64) {
+        let mut cpu = Chip8::with_config(&CONFIG);
+        cpu.load_rom(&ROM).unwrap();
+        cpu.start_recording(Movie::new(cpu.rom_hash(), 7, &CONFIG));
+
+        for frame in 0..90 {
+            if frame == press {
+                cpu.on_key_down(1);
+            } else if frame == release {
+                cpu.on_key_up(1);
+            }
+
+            cpu.tick().unwrap();
+        }
+
+        (cpu.stop_recording().unwrap(), cpu.state_hash())
+    }
+
+    fn play(movie: Movie) -> Result<u64, Desync> {
+        let mut cpu = Chip8::with_config(&movie.config());
+        cpu.load_rom(&ROM).unwrap();
+        let mut player = MoviePlayer::new(movie);
+
+        while !player.finished() {
+            player.before_tick(&mut cpu);
+            cpu.tick().unwrap();
+            player.after_tick(&cpu)?;
+        }
+
+        Ok(cpu.state_hash())
+    }
+
+    #[test]
+    fn plays_back_what_it_records() {
+        let (movie, hash) = record(10, 40);
+        let text = movie.to_string();
+
+        assert!(text.contains("\nfont octo\nbig-font octo\n"));
+        assert!(text.contains("\ninput 10 0x0001\nhash 30 "));
+        assert!(text.contains("\ninput 40 0x0000\nhash 60 "));
+
+        let movie = text.parse::<Movie>().unwrap();
+        assert_eq!(movie.config(), CONFIG);
+        assert_eq!(play(movie), Ok(hash));
+    }
+
+    #[test]
+    fn detects_desyncs() {
+        let (mut movie, _) = record(35, 50);
+        movie.inputs[0].0 = 45;
+
+        let desync = play(movie).unwrap_err();
+        assert_eq!(
+            desync,
+            Desync {
+                frame: 60,
+                last_match: Some(30)
+            }
+        );
+        assert_eq!(
+            desync.to_string(),
+            "Movie desynced at frame 60 (last in sync at frame 30)"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::instruction::{decode, Instruction};
+
+/// Entry point of the code that runs outside of any subroutine.
+const ROOT: u16 = 0x200;
+const HOT_SPOTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    pub calls: u64,
+    /// Cycles spent in the subroutine and everything it called.
+    pub inclusive: u64,
+    /// Cycles spent in the subroutine's own instructions.
+    pub exclusive: u64,
+}
+
+/// Counts executed instructions per address and per subroutine. Calls are
+/// followed with a shadow stack of subroutine addresses built from CALL and
+/// RET, and every instruction counts as one cycle.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    addresses: HashMap<u16, u64>,
+    subroutines: HashMap<u16, Subroutine>,
+    stacks: HashMap<Vec<u16>, u64>,
+    stack: Vec<u16>,
+    cycles: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            addresses: HashMap::new(),
+            subroutines: HashMap::new(),
+            stacks: HashMap::new(),
+            stack: vec![ROOT],
+            cycles: 0,
+        }
+    }
+}
+
+impl Profiler {
+    /// Counts the instruction about to run at `pc`. `sp` is the machine's
+    /// stack pointer, used to recover from jumps in time such as rewinds.
+    pub fn record(&mut self, pc: u16, opcode: u16, sp: u8) {
+        self.stack.truncate(sp as usize + 1);
+        self.cycles += 1;
+        *self.addresses.entry(pc).or_default() += 1;
+
+        for (i, &address) in self.stack.iter().enumerate() {
+            // Recursive subroutines only count once
+            if !self.stack[..i].contains(&address) {
+                self.subroutines.entry(address).or_default().inclusive += 1;
+            }
+        }
+
+        let current = *self.stack.last().unwrap();
+        self.subroutines.entry(current).or_default().exclusive += 1;
+
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(cycles) => *cycles += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+
+        match decode(opcode) {
+            Instruction::Call(address) => {
+                self.subroutines.entry(address).or_default().calls += 1;
+                self.stack.push(address);
+            }
+            Instruction::Ret if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Hottest addresses and every subroutine, sorted by cycles. `memory` is
+    /// used to show the instruction at each address.
+    pub fn report(&self, memory: &[u8]) -> String {
+        let mut output = String::new();
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.cycles.max(1) as f64;
+
+        let mut addresses = self.addresses.iter().collect::<Vec<_>>();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        writeln!(output, "{} cycles", self.cycles).unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "Address      Cycles       %  Instruction").unwrap();
+
+        for (&address, &cycles) in addresses.iter().take(HOT_SPOTS) {
+            let instruction = memory
+                .get(address as usize..address as usize + 2)
+                .map(|bytes| decode(u16::from_be_bytes([bytes[0], bytes[1]])).to_string())
+                .unwrap_or_default();
+
+            writeln!(
+                output,
+                "{:#06X}  {:>10}  {:>5.1}%  {}",
+                address,
+                cycles,
+                percent(cycles),
+                instruction
+            )
+            .unwrap();
+        }
+
+        let mut subroutines = self.subroutines.iter().collect::<Vec<_>>();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+
+        writeln!(output).unwrap();
+        writeln!(
+            output,
+            "Subroutine       Calls   Inclusive       %   Exclusive       %"
+        )
+        .unwrap();
+
+        for (&address, subroutine) in subroutines {
+            writeln!(
+                output,
+                "{:<10}  {:>10}  {:>10}  {:>5.1}%  {:>10}  {:>5.1}%",
+                name(address),
+                subroutine.calls,
+                subroutine.inclusive,
+                percent(subroutine.inclusive),
+                subroutine.exclusive,
+                percent(subroutine.exclusive)
+            )
+            .unwrap();
+        }
+
+        output
+    }
+
+    /// One `main;sub_0300;sub_0340 <cycles>` line per call stack, the format
+    /// read by flame graph tools.
+    pub fn folded(&self) -> String {
+        let mut lines = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let frames = stack.iter().map(|&a| name(a)).collect::<Vec<_>>();
+                format!("{} {}\n", frames.join(";"), cycles)
+            })
+            .collect::<Vec<_>>();
+
+        lines.sort();
+        lines.concat()
+    }
+}
+
+fn name(address: u16) -> String {
+    if address == ROOT {
+        "main".to_string()
+    } else {
+        format!("sub_{:04X}", address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // 0x200: CALL 0x206; JP 0x202; 0x206: LD V0, 1; CALL 0x20C; RET;
+    // 0x20C: RET
+    const ROM: [u8; 14] = [
+        0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x01, 0x22, 0x0C, 0x00, 0xEE, 0x00, 0xEE,
+    ];
+
+    fn profile(cycles: usize) -> Profiler {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&ROM).unwrap();
+        cpu.start_profiling(Profiler::default());
+
+        for _ in 0..cycles {
+            cpu.emulate_cycle().unwrap();
+        }
+
+        cpu.stop_profiling().unwrap()
+    }
+
+    #[test]
+    fn counts_inclusive_and_exclusive_cycles() {
+        // CALL, LD, CALL, RET, RET, then JP three times
+        let profiler = profile(8);
+
+        assert_eq!(profiler.addresses[&0x202], 3);
+        assert_eq!(
+            profiler.subroutines.get(&0x200),
+            Some(&Subroutine {
+                calls: 0,
+                inclusive: 8,
+                exclusive: 4
+            })
+        );
+        assert_eq!(
+            profiler.subroutines.get(&0x206),
+            Some(&Subroutine {
+                calls: 1,
+                inclusive: 4,
+                exclusive: 3
+            })
+        );
+        assert_eq!(
+            profiler.subroutines.get(&0x20C),
+            Some(&Subroutine {
+                calls: 1,
+                inclusive: 1,
+                exclusive: 1
+            })
+        );
+    }
+
+    #[test]
+    fn writes_folded_stacks() {
+        assert_eq!(
+            profile(8).folded(),
+            "main 4\nmain;sub_0206 3\nmain;sub_0206;sub_020C 1\n"
+        );
+    }
+
+    #[test]
+    fn report_is_sorted_by_cycles() {
+        let report = profile(8).report(&[0; 0x1000]);
+        let lines = report.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "8 cycles");
+        assert!(
+            lines[3].starts_with("0x0202           3   37.5%"),
+            "{}",
+            lines[3]
+        );
+        assert!(report.contains("main                 0           8  100.0%           4   50.0%"));
+    }
+}
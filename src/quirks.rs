@@ -0,0 +1,214 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How far `Fx55` and `Fx65` move I after touching V0..Vx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStoreIncrement {
+    /// I is left alone.
+    None,
+    /// I ends on Vx's byte, as on the CHIP-48.
+    X,
+    /// I ends just past Vx's byte, as on the COSMAC VIP.
+    XPlus1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,
+    pub load_store_increment: LoadStoreIncrement,
+    pub jump_uses_vx: bool,
+    pub vf_reset: bool,
+    pub clip_sprites: bool,
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: LoadStoreIncrement::XPlus1,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true,
+    };
+
+    /// SUPER-CHIP's ancestor on the HP-48, which still moved I on `Fx55`
+    /// and `Fx65`, but one byte less than the VIP.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::X,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::None,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: LoadStoreIncrement::XPlus1,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+    };
+
+    pub const NONE: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::None,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+    };
+
+    fn flags(&self) -> [(&'static str, bool); 7] {
+        [
+            ("shift-uses-vy", self.shift_uses_vy),
+            (
+                "load-store-increments-i",
+                self.load_store_increment == LoadStoreIncrement::XPlus1,
+            ),
+            (
+                "load-store-increments-i-by-x",
+                self.load_store_increment == LoadStoreIncrement::X,
+            ),
+            ("jump-uses-vx", self.jump_uses_vx),
+            ("vf-reset", self.vf_reset),
+            ("clip-sprites", self.clip_sprites),
+            ("display-wait", self.display_wait),
+        ]
+    }
+
+    /// Turns on the quirk called `name`, returning false for unknown names.
+    fn enable(&mut self, name: &str) -> bool {
+        match name {
+            "shift-uses-vy" => self.shift_uses_vy = true,
+            "load-store-increments-i" => self.load_store_increment = LoadStoreIncrement::XPlus1,
+            "load-store-increments-i-by-x" => self.load_store_increment = LoadStoreIncrement::X,
+            "jump-uses-vx" => self.jump_uses_vx = true,
+            "vf-reset" => self.vf_reset = true,
+            "clip-sprites" => self.clip_sprites = true,
+            "display-wait" => self.display_wait = true,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// No quirks, which is how the emulator behaved before quirks could be
+/// chosen. The command line uses this too unless `--quirks` or
+/// `--xo-chip` is given.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::NONE
+    }
+}
+
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled = self
+            .flags()
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+
+        if enabled.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(","))
+        }
+    }
+}
+
+/// Accepts either a preset name (`vip`, `chip48`, `schip`, `xochip`, `none`)
+/// or a comma separated list of quirk names, e.g. `shift-uses-vy,vf-reset`.
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vip" | "cosmac-vip" | "chip8" | "chip-8" => return Ok(Quirks::COSMAC_VIP),
+            "chip48" | "chip-48" => return Ok(Quirks::CHIP_48),
+            "schip" | "superchip" | "super-chip" => return Ok(Quirks::SUPER_CHIP),
+            "xochip" | "xo-chip" => return Ok(Quirks::XO_CHIP),
+            "none" | "" => return Ok(Quirks::NONE),
+            _ => {}
+        }
+
+        let mut quirks = Quirks::NONE;
+
+        for name in s.split(',').map(str::trim) {
+            if !quirks.enable(&name.to_lowercase()) {
+                return Err(format!("Unknown quirk: {}", name));
+            }
+        }
+
+        Ok(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [(&str, Quirks); 5] = [
+        ("vip", Quirks::COSMAC_VIP),
+        ("chip48", Quirks::CHIP_48),
+        ("schip", Quirks::SUPER_CHIP),
+        ("xochip", Quirks::XO_CHIP),
+        ("none", Quirks::NONE),
+    ];
+
+    #[test]
+    fn default_keeps_the_original_behaviour() {
+        assert_eq!(Quirks::default(), Quirks::NONE);
+    }
+
+    #[test]
+    fn presets_are_distinct() {
+        for (i, (name, quirks)) in PRESETS.iter().enumerate() {
+            for (other, other_quirks) in &PRESETS[i + 1..] {
+                assert_ne!(quirks, other_quirks, "{} and {}", name, other);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_presets_and_lists() {
+        for (name, quirks) in PRESETS {
+            assert_eq!(name.parse::<Quirks>(), Ok(quirks));
+            assert_eq!(quirks.to_string().parse::<Quirks>(), Ok(quirks));
+        }
+
+        assert_eq!(
+            "jump-uses-vx, VF-RESET".parse::<Quirks>(),
+            Ok(Quirks {
+                jump_uses_vx: true,
+                vf_reset: true,
+                ..Quirks::NONE
+            })
+        );
+        assert_eq!(
+            "load-store-increments-i-by-x".parse::<Quirks>(),
+            Ok(Quirks {
+                load_store_increment: LoadStoreIncrement::X,
+                ..Quirks::NONE
+            })
+        );
+        assert_eq!(
+            "shift-uses-vy,wrap".parse::<Quirks>(),
+            Err("Unknown quirk: wrap".to_string())
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of per-frame snapshots. Only the newest snapshot is kept in
+/// full; every older one is stored as the XOR against its successor with
+/// runs of unchanged bytes collapsed, which is usually a handful of bytes.
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+
+            self.deltas.push_back(compress(&previous, &snapshot));
+        }
+
+        self.latest = Some(snapshot);
+    }
+
+    /// Steps back up to `frames` snapshots and returns how many were undone
+    /// together with the snapshot reached.
+    pub fn rewind(&mut self, frames: usize) -> Option<(usize, &[u8])> {
+        let snapshot = self.latest.as_mut()?;
+        let steps = frames.min(self.deltas.len());
+
+        for _ in 0..steps {
+            apply(snapshot, &self.deltas.pop_back().unwrap());
+        }
+
+        Some((steps, snapshot))
+    }
+}
+
+/// Encodes `older ^ newer` as pairs of varint run lengths, unchanged bytes
+/// followed by changed ones, with the changed XOR bytes inlined.
+fn compress(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut delta = vec![];
+    let mut i = 0;
+
+    while i < older.len() {
+        let start = i;
+        while i < older.len() && older[i] == newer[i] {
+            i += 1;
+        }
+        let unchanged = i - start;
+
+        let start = i;
+        while i < older.len() && older[i] != newer[i] {
+            i += 1;
+        }
+
+        write_varint(&mut delta, unchanged);
+        write_varint(&mut delta, i - start);
+        delta.extend((start..i).map(|j| older[j] ^ newer[j]));
+    }
+
+    delta
+}
+
+fn apply(snapshot: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        position += read_varint(delta, &mut i);
+        let changed = read_varint(delta, &mut i);
+
+        for byte in &mut snapshot[position..position + changed] {
+            *byte ^= delta[i];
+            i += 1;
+        }
+
+        position += changed;
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // 0x200: ADD V0, 1; LD I, 0x300; LD [I], V0; JP 0x200
+    const ROM: [u8; 8] = [0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+
+    fn machine(xo_chip: bool) -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.set_xo_chip(xo_chip);
+        cpu.load_rom(&ROM).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, usize::MAX] {
+            let mut data = vec![];
+            write_varint(&mut data, value);
+            let mut i = 0;
+            assert_eq!(read_varint(&data, &mut i), value);
+            assert_eq!(i, data.len());
+        }
+    }
+
+    #[test]
+    fn restores_the_exact_state() {
+        let mut cpu = machine(false);
+        cpu.set_rewind_capacity(30);
+        let mut hashes = vec![cpu.state_hash()];
+
+        for _ in 0..20 {
+            cpu.tick().unwrap();
+            hashes.push(cpu.state_hash());
+        }
+
+        assert_eq!(cpu.rewind(5), 5);
+        assert_eq!(cpu.frame(), 15);
+        assert_eq!(cpu.state_hash(), hashes[15]);
+
+        // Rewinding again continues from there, and stops at the start
+        assert_eq!(cpu.rewind(100), 15);
+        assert_eq!(cpu.state_hash(), hashes[0]);
+        assert_eq!(cpu.rewind(1), 0);
+
+        for hash in &hashes[1..] {
+            cpu.tick().unwrap();
+            assert_eq!(cpu.state_hash(), *hash);
+        }
+    }
+
+    #[test]
+    fn forgets_history_when_memory_size_changes() {
+        let mut cpu = machine(false);
+        cpu.set_rewind_capacity(30);
+        (0..5).for_each(|_| cpu.tick().unwrap());
+
+        cpu.set_xo_chip(true);
+        assert_eq!(cpu.rewind(5), 0);
+        cpu.tick().unwrap();
+        assert_eq!(cpu.rewind(5), 1);
+    }
+
+    #[test]
+    fn keeps_only_the_last_frames() {
+        let mut buffer = RewindBuffer::new(3);
+        (0..10u8).for_each(|frame| buffer.push(vec![frame; 4]));
+
+        assert_eq!(buffer.rewind(10), Some((3, &[6u8; 4][..])));
+        assert_eq!(buffer.rewind(1), Some((0, &[6u8; 4][..])));
+
+        buffer.clear();
+        assert_eq!(buffer.rewind(1), None);
+    }
+
+    #[test]
+    fn deltas_are_much_smaller_than_snapshots() {
+        for xo_chip in [false, true] {
+            let mut cpu = machine(xo_chip);
+            let mut buffer = RewindBuffer::new(10);
+
+            for _ in 0..10 {
+                cpu.tick().unwrap();
+                buffer.push(cpu.save_state());
+            }
+
+            let snapshot = buffer.latest.as_ref().unwrap().len();
+            assert_eq!(buffer.deltas.len(), 9);
+            assert!(buffer.deltas.iter().all(|delta| delta.len() < 64));
+            // Only the active memory is stored
+            assert_eq!(snapshot < 0x2000, !xo_chip);
+            assert!(snapshot > cpu.memory().len());
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Source of the bytes returned by CXNN before they are masked with NN.
+/// The state must round-trip through `state`/`set_state` so save states and
+/// rewinds replay the same numbers.
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+    fn clone_box(&self) -> Box<dyn RandomSource>;
+}
+
+impl Clone for Box<dyn RandomSource> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// xorshift64* generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Xorshift { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        // xorshift never leaves the all-zero state
+        self.state = if state == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            state
+        };
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+}
+
+/// The routine used by the original COSMAC VIP interpreter: a 16-bit
+/// counter (register R9 on the 1802) is incremented, its low byte picks a
+/// byte from the page of the interpreter the routine runs in, and the result
+/// is mixed into its high byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CosmacVip {
+    r9: u16,
+}
+
+/// Second page (0x0100-0x01FF) of the COSMAC VIP CHIP-8 interpreter. This
+/// emulator keeps its fonts there, so the bytes are kept apart from memory.
+const VIP_INTERPRETER_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC, 0x22,
+    0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A, 0xF4,
+    0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA, 0x0A,
+    0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A, 0x0E,
+    0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F, 0x56,
+    0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17, 0x1A,
+    0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17, 0x1A,
+    0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA, 0x0F,
+    0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88, 0xD4,
+    0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88, 0xD4,
+    0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2, 0xFC,
+    0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A, 0xC4,
+    0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2, 0x56,
+    0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE, 0xF4,
+    0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F, 0xBA,
+    0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00, 0x4B,
+];
+
+impl CosmacVip {
+    pub fn new(seed: u64) -> Self {
+        CosmacVip { r9: seed as u16 }
+    }
+}
+
+impl RandomSource for CosmacVip {
+    fn next_byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+
+        let [high, low] = self.r9.to_be_bytes();
+        let (sum, carry) = VIP_INTERPRETER_PAGE[low as usize].overflowing_add(high);
+        // SHRC shifts the carry of the previous ADD into bit 7
+        let shifted = sum >> 1 | (carry as u8) << 7;
+        let result = sum.wrapping_add(shifted);
+
+        self.r9 = u16::from_be_bytes([result, low]);
+        result
+    }
+
+    fn state(&self) -> u64 {
+        self.r9 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.r9 = state as u16;
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RandomMode {
+    #[default]
+    Xorshift,
+    CosmacVip,
+}
+
+impl RandomMode {
+    pub fn source(&self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            RandomMode::Xorshift => Box::new(Xorshift::new(seed)),
+            RandomMode::CosmacVip => Box::new(CosmacVip::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for RandomMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RandomMode::Xorshift => write!(f, "xorshift"),
+            RandomMode::CosmacVip => write!(f, "vip"),
+        }
+    }
+}
+
+impl FromStr for RandomMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xorshift" => Ok(RandomMode::Xorshift),
+            "vip" | "cosmac-vip" => Ok(RandomMode::CosmacVip),
+            _ => Err(format!("Unknown random mode: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(rng: &mut dyn RandomSource, count: usize) -> Vec<u8> {
+        (0..count).map(|_| rng.next_byte()).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_bytes() {
+        for mode in [RandomMode::Xorshift, RandomMode::CosmacVip] {
+            let first = bytes(&mut *mode.source(7), 64);
+
+            assert_eq!(bytes(&mut *mode.source(7), 64), first, "{}", mode);
+            assert_ne!(bytes(&mut *mode.source(8), 64), first, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn state_replays_the_sequence() {
+        for mode in [RandomMode::Xorshift, RandomMode::CosmacVip] {
+            let mut rng = mode.source(1);
+            bytes(&mut *rng, 10);
+
+            let state = rng.state();
+            let next = bytes(&mut *rng, 32);
+            rng.set_state(state);
+            assert_eq!(bytes(&mut *rng, 32), next, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn vip_routine_mixes_the_interpreter_page() {
+        let mut rng = CosmacVip::new(0);
+
+        // R9 = 0x0001: 0x00 + page[1], then added to itself shifted right
+        let sum = VIP_INTERPRETER_PAGE[1];
+        assert_eq!(rng.next_byte(), sum.wrapping_add(sum >> 1));
+
+        let values = bytes(&mut rng, 256);
+        let distinct = values.iter().collect::<std::collections::BTreeSet<_>>();
+        assert!(distinct.len() > 64, "{:?}", values);
+    }
+}
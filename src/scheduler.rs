@@ -0,0 +1,134 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// How many frames the scheduler may fall behind before it stops trying to catch up
+const MAX_FRAME_LAG: u32 = 5;
+
+struct SchedulerState {
+    frame: u64,
+    stopped: bool,
+}
+
+pub struct FrameScheduler {
+    interval: Duration,
+    state: Mutex<SchedulerState>,
+    frame_ready: Condvar,
+}
+
+impl FrameScheduler {
+    pub fn new(frame_rate: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / frame_rate,
+            state: Mutex::new(SchedulerState {
+                frame: 0,
+                stopped: false,
+            }),
+            frame_ready: Condvar::new(),
+        }
+    }
+
+    /// Calls `on_frame` once per frame until it returns `false` or the
+    /// scheduler is stopped, publishing each completed frame to the threads
+    /// blocked in [`FrameScheduler::wait_frame`].
+    pub fn run(&self, mut on_frame: impl FnMut(u64) -> bool) {
+        let mut deadline = Instant::now();
+
+        loop {
+            let frame = {
+                let state = self.state.lock().unwrap();
+                if state.stopped {
+                    break;
+                }
+                state.frame
+            };
+
+            let keep_running = on_frame(frame);
+
+            self.state.lock().unwrap().frame += 1;
+            self.frame_ready.notify_all();
+
+            if !keep_running {
+                break;
+            }
+
+            deadline += self.interval;
+            let now = Instant::now();
+
+            if deadline > now {
+                std::thread::sleep(deadline - now);
+            } else if now - deadline > self.interval * MAX_FRAME_LAG {
+                deadline = now;
+            }
+        }
+
+        self.stop();
+    }
+
+    /// Blocks until a frame newer than `last` is published or the scheduler
+    /// is stopped, returning the latest frame number. Gives up after one
+    /// frame interval so callers keep responding to their own events even
+    /// when no frames are produced.
+    pub fn wait_frame(&self, last: u64) -> u64 {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .frame_ready
+            .wait_timeout_while(state, self.interval, |state| {
+                state.frame <= last && !state.stopped
+            })
+            .unwrap();
+        state.frame
+    }
+
+    pub fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.frame_ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn wait_frame_paces_frames() {
+        let scheduler = Arc::new(FrameScheduler::new(100));
+        let producer = scheduler.clone();
+        let start = Instant::now();
+        let t1 = std::thread::spawn(move || producer.run(|frame| frame < 5));
+
+        let mut frames = vec![];
+        let mut frame = 0;
+        while frame < 6 {
+            frame = scheduler.wait_frame(frame);
+            frames.push(frame);
+        }
+        t1.join().unwrap();
+
+        // Six frames at 100 Hz take at least the five intervals between them
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(frames.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(frames.last(), Some(&6));
+    }
+
+    #[test]
+    fn stop_unblocks_a_waiting_thread() {
+        // One frame a minute, so only `stop` can wake the waiter in time
+        let scheduler = Arc::new(FrameScheduler {
+            interval: Duration::from_secs(60),
+            ..FrameScheduler::new(1)
+        });
+        let waiter = scheduler.clone();
+        let t1 = std::thread::spawn(move || {
+            let start = Instant::now();
+            waiter.wait_frame(0);
+            start.elapsed()
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        scheduler.stop();
+
+        assert!(t1.join().unwrap() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,297 @@
+use sdl2::audio::{AudioDevice, AudioStatus};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::{EventPump, Sdl};
+
+use chip8::frontend::{
+    self, AudioSink, Display, InputEvent, InputSource, RunOptions, Screen, Sound, Status,
+};
+use chip8::Chip8;
+
+use crate::audio::{Beeper, PatternPlayer, SquareWave};
+
+pub struct SdlOptions {
+    pub palette: [[u8; 3]; 4],
+    pub audio_freq: f32,
+    pub xo_chip: bool,
+}
+
+fn to_color([r, g, b]: [u8; 3]) -> Color {
+    Color::RGB(r, g, b)
+}
+
+/// Runs the machine in real time in a window until it is closed.
+pub fn run(chip8: Chip8, options: RunOptions, sdl_options: SdlOptions) -> Chip8 {
+    // Inicializa SDL
+    let sdl_context: Sdl = sdl2::init().unwrap();
+
+    let mut display = SdlDisplay::new(&sdl_context, sdl_options.palette);
+    let mut audio = SdlAudio::new(&sdl_context, sdl_options.audio_freq, sdl_options.xo_chip);
+    let mut input = SdlInput {
+        event_pump: sdl_context.event_pump().unwrap(),
+    };
+
+    frontend::run(chip8, options, &mut display, &mut audio, &mut input)
+}
+
+struct SdlDisplay {
+    canvas: Canvas<Window>,
+    palette: [Color; 4],
+    crashed: bool,
+}
+
+impl SdlDisplay {
+    fn new(sdl_context: &Sdl, palette: [[u8; 3]; 4]) -> Self {
+        let video_subsystem = sdl_context.video().unwrap();
+
+        // Cria uma janela
+        let window = video_subsystem
+            .window("Emulador Chip-8", 640, 320)
+            .position_centered()
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())
+            .unwrap();
+
+        let mut canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|e| e.to_string())
+            .unwrap();
+
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+
+        let palette = palette.map(to_color);
+
+        // Limpa a tela
+        canvas.set_draw_color(palette[0]);
+        canvas.clear();
+
+        SdlDisplay {
+            canvas,
+            palette,
+            crashed: false,
+        }
+    }
+}
+
+impl Display for SdlDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        let [background, foreground, color2, color3] = self.palette;
+
+        // Mostra o erro na barra de título e congela a tela em vermelho
+        if screen.error.is_some() != self.crashed {
+            self.crashed = screen.error.is_some();
+            let title = match &screen.error {
+                Some(e) => format!("Emulador Chip-8 - {}", e),
+                None => "Emulador Chip-8".to_string(),
+            };
+            self.canvas.window_mut().set_title(&title).unwrap();
+        }
+
+        let palette = if self.crashed {
+            [background, Color::RGB(255, 0, 0), color2, color3]
+        } else {
+            [background, foreground, color2, color3]
+        };
+
+        let width = screen.width as u32;
+        let height = screen.height as u32;
+
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let scale_x = window_width / width;
+        let scale_y = window_height / height;
+        let scale = scale_x.min(scale_y);
+
+        // Desenha o buffer na tela
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = screen.pixels[(y * width + x) as usize];
+
+                let color = if pixel == 0 {
+                    // Simula o efeito de fade dos monitores CRT
+                    Color::RGBA(background.r, background.g, background.b, 48)
+                } else {
+                    palette[pixel as usize]
+                };
+
+                let rect = Rect::new(
+                    x as i32 * scale as i32,
+                    y as i32 * scale as i32,
+                    scale,
+                    scale,
+                );
+
+                self.canvas.set_draw_color(color);
+                self.canvas.fill_rect(rect).unwrap();
+            }
+        }
+
+        // Atualiza o canvas
+        self.canvas.present();
+    }
+
+    // A janela não tem onde mostrar mensagens, então elas vão para o terminal
+    fn report(&mut self, status: &Status) {
+        match status {
+            Status::Crashed(e) => eprintln!("{}", e),
+            status => println!("{}", status),
+        }
+    }
+}
+
+struct SdlAudio {
+    device: AudioDevice<Beeper>,
+    xo_chip: bool,
+}
+
+impl SdlAudio {
+    fn new(sdl_context: &Sdl, audio_freq: f32, xo_chip: bool) -> Self {
+        let audio_subsystem = sdl_context.audio().unwrap();
+
+        let desired_spec = sdl2::audio::AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem
+            .open_playback(None, &desired_spec, |spec| {
+                // initialize the audio callback
+                if xo_chip {
+                    Beeper::Pattern(PatternPlayer {
+                        pattern: [0; 16],
+                        rate: 0.0,
+                        sample_rate: spec.freq as f32,
+                        position: 0.0,
+                        volume: 0.25,
+                    })
+                } else {
+                    Beeper::Square(SquareWave {
+                        phase_inc: audio_freq / spec.freq as f32,
+                        phase: 0.0,
+                        volume: 0.25,
+                    })
+                }
+            })
+            .unwrap();
+
+        SdlAudio { device, xo_chip }
+    }
+}
+
+impl AudioSink for SdlAudio {
+    fn update(&mut self, sound: &Sound) {
+        if self.xo_chip {
+            if let Beeper::Pattern(player) = &mut *self.device.lock() {
+                player.pattern = sound.pattern;
+                player.rate = sound.rate;
+            }
+        }
+
+        let playing = self.device.status() == AudioStatus::Playing;
+
+        if sound.beeping && !playing {
+            self.device.resume();
+        } else if !sound.beeping && playing {
+            self.device.pause();
+        }
+    }
+}
+
+struct SdlInput {
+    event_pump: EventPump,
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = vec![];
+
+        for event in self.event_pump.poll_iter() {
+            let event = match event {
+                sdl2::event::Event::Quit { .. } => InputEvent::Quit,
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if get_state_slot(key).is_some() => {
+                    let save = keymod.intersects(
+                        sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD,
+                    );
+                    let slot = get_state_slot(key).unwrap();
+
+                    if save {
+                        InputEvent::SaveState(slot)
+                    } else {
+                        InputEvent::LoadState(slot)
+                    }
+                }
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::Backspace),
+                    ..
+                } => InputEvent::Rewind(true),
+                sdl2::event::Event::KeyUp {
+                    keycode: Some(sdl2::keyboard::Keycode::Backspace),
+                    ..
+                } => InputEvent::Rewind(false),
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(key), ..
+                } if get_key(key).is_some() => InputEvent::KeyDown(get_key(key).unwrap()),
+                sdl2::event::Event::KeyUp {
+                    keycode: Some(key), ..
+                } if get_key(key).is_some() => InputEvent::KeyUp(get_key(key).unwrap()),
+                // sdl2::event::Event::MouseButtonDown { window_id, .. }
+                // | sdl2::event::Event::MouseButtonUp { window_id, .. }
+                //     if window_id == keypad.window_id =>
+                // {
+                //     keypad.handle_event(event);
+                //     keypad.draw();
+                // }
+                _ => continue,
+            };
+
+            events.push(event);
+        }
+
+        events
+    }
+}
+
+fn get_state_slot(key: sdl2::keyboard::Keycode) -> Option<u8> {
+    match key {
+        sdl2::keyboard::Keycode::F1 => Some(1),
+        sdl2::keyboard::Keycode::F2 => Some(2),
+        sdl2::keyboard::Keycode::F3 => Some(3),
+        sdl2::keyboard::Keycode::F4 => Some(4),
+        sdl2::keyboard::Keycode::F5 => Some(5),
+        sdl2::keyboard::Keycode::F6 => Some(6),
+        sdl2::keyboard::Keycode::F7 => Some(7),
+        sdl2::keyboard::Keycode::F8 => Some(8),
+        sdl2::keyboard::Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+fn get_key(key: sdl2::keyboard::Keycode) -> Option<u8> {
+    match key {
+        sdl2::keyboard::Keycode::Num0 => Some(0x0),
+        sdl2::keyboard::Keycode::Num1 => Some(0x1),
+        sdl2::keyboard::Keycode::Num2 => Some(0x2),
+        sdl2::keyboard::Keycode::Num3 => Some(0x3),
+        sdl2::keyboard::Keycode::Num4 => Some(0x4),
+        sdl2::keyboard::Keycode::Num5 => Some(0x5),
+        sdl2::keyboard::Keycode::Num6 => Some(0x6),
+        sdl2::keyboard::Keycode::Num7 => Some(0x7),
+        sdl2::keyboard::Keycode::Num8 => Some(0x8),
+        sdl2::keyboard::Keycode::Num9 => Some(0x9),
+        sdl2::keyboard::Keycode::A => Some(0xA),
+        sdl2::keyboard::Keycode::B => Some(0xB),
+        sdl2::keyboard::Keycode::C => Some(0xC),
+        sdl2::keyboard::Keycode::D => Some(0xD),
+        sdl2::keyboard::Keycode::E => Some(0xE),
+        sdl2::keyboard::Keycode::F => Some(0xF),
+        _ => None,
+    }
+}
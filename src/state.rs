@@ -0,0 +1,254 @@
+use crate::error::StateError;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const VERSION: u16 = 4;
+const HEADER_SIZE: usize = MAGIC.len() + 2 + 8;
+const CHECKSUM_SIZE: usize = 4;
+
+/// 64-bit FNV-1a, used to tie a save state to the ROM it was taken from.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Wraps a snapshot in a save state: header (magic, version, ROM hash),
+/// the snapshot itself and a trailing CRC-32 of everything before it.
+pub fn encode(rom_hash: u64, snapshot: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_SIZE + snapshot.len() + CHECKSUM_SIZE);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&rom_hash.to_le_bytes());
+    data.extend_from_slice(snapshot);
+
+    let checksum = crc32(&data);
+    data.extend_from_slice(&checksum.to_le_bytes());
+    data
+}
+
+/// Validates the header and checksum of a save state and returns the
+/// snapshot inside it.
+pub fn decode(state: &[u8], rom_hash: u64) -> Result<&[u8], StateError> {
+    if state.len() < HEADER_SIZE + CHECKSUM_SIZE || &state[..MAGIC.len()] != MAGIC {
+        return Err(StateError::InvalidHeader);
+    }
+
+    let version = u16::from_le_bytes([state[4], state[5]]);
+
+    if version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+
+    let (data, checksum) = state.split_at(state.len() - CHECKSUM_SIZE);
+
+    if crc32(data).to_le_bytes() != checksum {
+        return Err(StateError::Corrupted);
+    }
+
+    if u64::from_le_bytes(data[6..HEADER_SIZE].try_into().unwrap()) != rom_hash {
+        return Err(StateError::RomMismatch);
+    }
+
+    Ok(&data[HEADER_SIZE..])
+}
+
+/// Serializes machine fields in order. All values are little endian.
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, position: 0 }
+    }
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or(StateError::SizeMismatch)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn fill(&mut self, target: &mut [u8]) -> Result<(), StateError> {
+        target.copy_from_slice(self.bytes(target.len())?);
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.position == self.data.len() {
+            Ok(())
+        } else {
+            Err(StateError::SizeMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // 0x200: RND V0, 0xFF; ADD V1, 1; LD I, 0x300; LD [I], V1; JP 0x200
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0x71, 0x01, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00];
+
+    fn machine() -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&ROM).unwrap();
+        cpu
+    }
+
+    /// Re-signs a state after it has been edited so only the edit is wrong.
+    fn resign(state: &mut Vec<u8>) {
+        state.truncate(state.len() - CHECKSUM_SIZE);
+        let checksum = crc32(state);
+        state.extend_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn hashes_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(fnv1a(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+    }
+
+    #[test]
+    fn restores_the_machine() {
+        let mut cpu = machine();
+        (0..5).for_each(|_| cpu.tick().unwrap());
+        let state = cpu.save_state();
+        let hash = cpu.state_hash();
+
+        assert_eq!(&state[..4], b"C8ST");
+        assert_eq!(state[4..6], VERSION.to_le_bytes());
+
+        (0..5).for_each(|_| cpu.tick().unwrap());
+        let later = cpu.state_hash();
+        assert_ne!(later, hash);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.state_hash(), hash);
+        (0..5).for_each(|_| cpu.tick().unwrap());
+        assert_eq!(cpu.state_hash(), later);
+    }
+
+    #[test]
+    fn rejects_states_of_another_rom() {
+        let state = machine().save_state();
+        let mut other = Chip8::new();
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        let hash = other.state_hash();
+
+        assert_eq!(other.load_state(&state), Err(StateError::RomMismatch));
+        assert_eq!(other.state_hash(), hash);
+    }
+
+    #[test]
+    fn rejects_corrupted_states() {
+        let mut cpu = machine();
+        let mut state = cpu.save_state();
+        let hash = cpu.state_hash();
+        state[HEADER_SIZE + 0x200] ^= 0xFF;
+
+        assert_eq!(cpu.load_state(&state), Err(StateError::Corrupted));
+        assert_eq!(cpu.state_hash(), hash);
+
+        assert_eq!(
+            cpu.load_state(&state[..HEADER_SIZE]),
+            Err(StateError::InvalidHeader)
+        );
+        assert_eq!(
+            cpu.load_state(b"not a save state"),
+            Err(StateError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut cpu = machine();
+        let hash = cpu.state_hash();
+
+        for version in [VERSION - 1, VERSION + 1] {
+            let mut state = cpu.save_state();
+            state[4..6].copy_from_slice(&version.to_le_bytes());
+            resign(&mut state);
+
+            assert_eq!(
+                cpu.load_state(&state),
+                Err(StateError::UnsupportedVersion(version))
+            );
+            assert_eq!(cpu.state_hash(), hash);
+        }
+    }
+
+    #[test]
+    fn rejects_snapshots_of_the_wrong_size() {
+        let mut cpu = machine();
+        let mut state = cpu.save_state();
+        state.insert(state.len() - CHECKSUM_SIZE, 0);
+        resign(&mut state);
+
+        assert_eq!(cpu.load_state(&state), Err(StateError::SizeMismatch));
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Label addresses written next to an assembled ROM, one `0x0202 name` per
+/// line, so the emulator can show names instead of raw addresses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u16, Vec<String>>,
+}
+
+impl SymbolTable {
+    pub fn insert(&mut self, address: u16, name: &str) {
+        let names = self.symbols.entry(address).or_default();
+        names.push(name.to_string());
+        names.sort();
+    }
+
+    /// The first name, in alphabetical order, given to an address.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .get(&address)
+            .and_then(|names| names.first())
+            .map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|(_, names)| names.iter().any(|n| n == name))
+            .map(|(&address, _)| address)
+    }
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, names) in &self.symbols {
+            for name in names {
+                writeln!(f, "{:#06X} {}", address, name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for SymbolTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols = SymbolTable::default();
+
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || format!("Line {}: expected <address> <name>", index + 1);
+            let (address, name) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let address = address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_prefix("0X"))
+                .and_then(|digits| u16::from_str_radix(digits, 16).ok())
+                .ok_or_else(error)?;
+
+            symbols.insert(address, name.trim());
+        }
+
+        Ok(symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut symbols = SymbolTable::default();
+        symbols.insert(0x200, "main");
+        symbols.insert(0x20A, "sprite");
+        symbols.insert(0x20A, "data");
+
+        let text = symbols.to_string();
+        assert_eq!(text, "0x0200 main\n0x020A data\n0x020A sprite\n");
+        assert_eq!(text.parse::<SymbolTable>(), Ok(symbols.clone()));
+
+        assert_eq!(symbols.name(0x20A), Some("data"));
+        assert_eq!(symbols.address("sprite"), Some(0x20A));
+        assert_eq!(symbols.address("missing"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(
+            "0x0200 main\n0200 loop".parse::<SymbolTable>(),
+            Err("Line 2: expected <address> <name>".to_string())
+        );
+        assert!("0x0200".parse::<SymbolTable>().is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+
+use chip8::frontend::{
+    self, AudioSink, Display, InputEvent, InputSource, RunOptions, Screen, Sound, Status,
+};
+use chip8::Chip8;
+
+// Terminais não avisam quando uma tecla é solta, então cada tecla fica
+// pressionada por alguns quadros depois do último caractere recebido
+const KEY_HOLD_FRAMES: u8 = 6;
+const CTRL_C: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Two pixels per character, in any two colours.
+    HalfBlock,
+    /// Eight pixels per character, in one colour.
+    Braille,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halfblock" => Ok(Mode::HalfBlock),
+            "braille" => Ok(Mode::Braille),
+            _ => Err(format!("Invalid terminal mode: {}", s)),
+        }
+    }
+}
+
+/// Runs the machine in real time on the terminal until Ctrl+C is pressed.
+pub fn run(chip8: Chip8, options: RunOptions, palette: [[u8; 3]; 4], mode: Mode) -> Chip8 {
+    let raw_mode = RawMode::enable().unwrap_or_else(|e| {
+        println!("Could not set up the terminal: {}", e);
+        std::process::exit(1);
+    });
+
+    let beeping = Rc::new(Cell::new(false));
+    let mut display = TerminalDisplay {
+        palette,
+        mode,
+        beeping: beeping.clone(),
+        message: None,
+        last: None,
+    };
+    let mut audio = TerminalAudio { beeping };
+    let mut input = TerminalInput::new();
+
+    // Esconde o cursor e limpa a tela
+    print!("\x1b[?25l\x1b[2J");
+
+    let chip8 = frontend::run(chip8, options, &mut display, &mut audio, &mut input);
+
+    print!("\x1b[0m\x1b[?25h\r\n");
+    io::stdout().flush().ok();
+    drop(raw_mode);
+
+    chip8
+}
+
+/// Puts the terminal in raw mode with `stty`, restoring it when dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        Ok(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]).ok();
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+struct TerminalDisplay {
+    palette: [[u8; 3]; 4],
+    mode: Mode,
+    beeping: Rc<Cell<bool>>,
+    /// The last status reported, shown until the next one.
+    message: Option<String>,
+    last: Option<(Screen, bool)>,
+}
+
+impl Display for TerminalDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        let beeping = self.beeping.get();
+
+        // Só redesenha quando algo mudou, para não saturar conexões SSH
+        if self
+            .last
+            .as_ref()
+            .is_some_and(|(last, last_beeping)| last == screen && *last_beeping == beeping)
+        {
+            return;
+        }
+
+        let frame = render(
+            screen,
+            self.palette,
+            self.mode,
+            beeping,
+            self.message.as_deref(),
+        );
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(frame.as_bytes()).ok();
+        stdout.flush().ok();
+
+        self.last = Some((screen.clone(), beeping));
+    }
+
+    fn report(&mut self, status: &Status) {
+        // O erro já aparece na linha de status junto com a tela vermelha
+        if !matches!(status, Status::Crashed(_)) {
+            self.message = Some(status.to_string());
+            self.last = None;
+        }
+    }
+}
+
+struct TerminalAudio {
+    beeping: Rc<Cell<bool>>,
+}
+
+impl AudioSink for TerminalAudio {
+    fn update(&mut self, sound: &Sound) {
+        self.beeping.set(sound.beeping);
+    }
+}
+
+struct TerminalInput {
+    bytes: Receiver<u8>,
+    held: [u8; 16],
+}
+
+impl TerminalInput {
+    fn new() -> Self {
+        let (sender, bytes) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for byte in io::stdin().lock().bytes().map_while(Result::ok) {
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+
+        TerminalInput {
+            bytes,
+            held: [0; 16],
+        }
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let bytes = self.bytes.try_iter().collect::<Vec<_>>();
+        key_events(&bytes, &mut self.held)
+    }
+}
+
+/// Turns the characters typed since the last poll into key events, using
+/// `held` to release each key a few frames after it was last typed.
+fn key_events(bytes: &[u8], held: &mut [u8; 16]) -> Vec<InputEvent> {
+    let mut events = vec![];
+    let mut typed = [false; 16];
+
+    for &byte in bytes {
+        if byte == CTRL_C {
+            events.push(InputEvent::Quit);
+            continue;
+        }
+
+        // Mesmo mapa de teclas hexadecimal da janela SDL
+        let Some(key) = (byte as char).to_digit(16) else {
+            continue;
+        };
+        let key = key as usize;
+
+        if held[key] == 0 && !typed[key] {
+            events.push(InputEvent::KeyDown(key as u8));
+        }
+
+        typed[key] = true;
+    }
+
+    for (key, frames) in held.iter_mut().enumerate() {
+        if typed[key] {
+            *frames = KEY_HOLD_FRAMES;
+        } else if *frames > 0 {
+            *frames -= 1;
+
+            if *frames == 0 {
+                events.push(InputEvent::KeyUp(key as u8));
+            }
+        }
+    }
+
+    events
+}
+
+fn foreground(output: &mut String, [r, g, b]: [u8; 3]) {
+    write!(output, "\x1b[38;2;{};{};{}m", r, g, b).unwrap();
+}
+
+fn background(output: &mut String, [r, g, b]: [u8; 3]) {
+    write!(output, "\x1b[48;2;{};{};{}m", r, g, b).unwrap();
+}
+
+/// Draws the screen from the top left corner of the terminal, with a status
+/// line below it showing the beeper, any error and the last message.
+fn render(
+    screen: &Screen,
+    mut palette: [[u8; 3]; 4],
+    mode: Mode,
+    beeping: bool,
+    message: Option<&str>,
+) -> String {
+    let mut output = String::from("\x1b[H");
+    let pixel = |x: usize, y: usize| {
+        if x < screen.width && y < screen.height {
+            screen.pixels[y * screen.width + x] as usize
+        } else {
+            0
+        }
+    };
+
+    // Congela a tela em vermelho quando a máquina trava, como na janela
+    if screen.error.is_some() {
+        palette[1] = [255, 0, 0];
+    }
+
+    match mode {
+        Mode::HalfBlock => {
+            for y in (0..screen.height).step_by(2) {
+                let mut colors = None;
+
+                for x in 0..screen.width {
+                    let cell = (pixel(x, y), pixel(x, y + 1));
+
+                    if colors != Some(cell) {
+                        foreground(&mut output, palette[cell.0]);
+                        background(&mut output, palette[cell.1]);
+                        colors = Some(cell);
+                    }
+
+                    output.push('▀');
+                }
+
+                output.push_str("\x1b[0m\r\n");
+            }
+        }
+        Mode::Braille => {
+            // Bit de cada ponto de um caractere Braille, por linha e coluna
+            const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+            for y in (0..screen.height).step_by(4) {
+                let mut color = None;
+                background(&mut output, palette[0]);
+
+                for x in (0..screen.width).step_by(2) {
+                    let mut dots = 0;
+                    let mut brightest = 0;
+
+                    for (dy, row) in DOTS.iter().enumerate() {
+                        for (dx, dot) in row.iter().enumerate() {
+                            let index = pixel(x + dx, y + dy);
+
+                            if index != 0 {
+                                dots |= dot;
+                                brightest = brightest.max(index);
+                            }
+                        }
+                    }
+
+                    if brightest != 0 && color != Some(brightest) {
+                        foreground(&mut output, palette[brightest]);
+                        color = Some(brightest);
+                    }
+
+                    output.push(char::from_u32(0x2800 + dots).unwrap());
+                }
+
+                output.push_str("\x1b[0m\r\n");
+            }
+        }
+    }
+
+    if beeping {
+        output.push_str("\x1b[7m \u{266A} BEEP \x1b[0m");
+    } else {
+        output.push_str("        ");
+    }
+
+    if let Some(error) = &screen.error {
+        write!(output, "  \x1b[31m{}\x1b[0m", error).unwrap();
+    }
+
+    if let Some(message) = message {
+        write!(output, "  {}", message).unwrap();
+    }
+
+    output.push_str("\x1b[K");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [1, 1, 1], [2, 2, 2]];
+
+    fn screen(width: usize, height: usize, lit: &[(usize, usize)]) -> Screen {
+        let mut pixels = vec![0; width * height];
+
+        for &(x, y) in lit {
+            pixels[y * width + x] = 1;
+        }
+
+        Screen {
+            width,
+            height,
+            pixels,
+            error: None,
+        }
+    }
+
+    fn text(output: &str) -> String {
+        let mut text = String::new();
+        let mut chars = output.chars();
+
+        // Remove as sequências de escape
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.find(|c| c.is_ascii_alphabetic());
+            } else if c != '\r' {
+                text.push(c);
+            }
+        }
+
+        text
+    }
+
+    #[test]
+    fn renders_half_blocks() {
+        let output = render(
+            &screen(2, 2, &[(0, 0)]),
+            PALETTE,
+            Mode::HalfBlock,
+            false,
+            None,
+        );
+
+        assert!(output.starts_with(
+            "\x1b[H\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m▀\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m▀"
+        ));
+        assert_eq!(text(&output), "▀▀\n        ");
+    }
+
+    #[test]
+    fn renders_braille() {
+        let lit = [(0, 0), (1, 1), (0, 3), (2, 0)];
+        let output = render(&screen(4, 4, &lit), PALETTE, Mode::Braille, false, None);
+
+        assert_eq!(text(&output), "\u{2851}\u{2801}\n        ");
+    }
+
+    #[test]
+    fn shows_beeper_and_error() {
+        let mut screen = screen(2, 2, &[(0, 0)]);
+        screen.error = Some(chip8::Chip8Error::StackUnderflow {
+            address: 0x200,
+            opcode: 0x00EE,
+        });
+
+        let output = render(&screen, PALETTE, Mode::HalfBlock, true, Some("Saved"));
+
+        assert!(output.contains("\x1b[38;2;255;0;0m\x1b[48;2;0;0;0m▀"));
+        assert!(text(&output).ends_with(" \u{266A} BEEP   Stack underflow by 00EE at 0x200  Saved"));
+    }
+
+    #[test]
+    fn holds_typed_keys_for_a_few_frames() {
+        let mut held = [0; 16];
+
+        assert_eq!(
+            key_events(b"5a5", &mut held),
+            [InputEvent::KeyDown(5), InputEvent::KeyDown(0xA)]
+        );
+
+        for _ in 1..KEY_HOLD_FRAMES {
+            assert_eq!(key_events(b"5", &mut held), []);
+            assert_eq!(held[5], KEY_HOLD_FRAMES);
+        }
+
+        assert_eq!(key_events(b"", &mut held), [InputEvent::KeyUp(0xA)]);
+        assert_eq!(key_events(b"xq\x03", &mut held), [InputEvent::Quit]);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::decode;
+
+/// Logs one line per executed instruction, with the machine state as it was
+/// before the instruction ran. With a ring size only the latest lines are
+/// kept, and they are written out when the machine hits an error.
+#[derive(Clone)]
+pub struct Tracer {
+    output: Arc<Mutex<dyn Write + Send>>,
+    range: Option<RangeInclusive<u16>>,
+    ring: Option<VecDeque<String>>,
+    ring_size: usize,
+    cycle: u64,
+}
+
+impl Tracer {
+    /// `ring_size` 0 writes every line as it happens.
+    pub fn new(
+        output: Arc<Mutex<dyn Write + Send>>,
+        range: Option<RangeInclusive<u16>>,
+        ring_size: usize,
+    ) -> Self {
+        Tracer {
+            output,
+            range,
+            ring: (ring_size > 0).then(|| VecDeque::with_capacity(ring_size)),
+            ring_size,
+            cycle: 0,
+        }
+    }
+
+    pub fn record(&mut self, cpu: &Chip8, opcode: u16) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+
+        let registers = cpu.registers();
+
+        if self
+            .range
+            .as_ref()
+            .is_some_and(|range| !range.contains(&registers.pc))
+        {
+            return;
+        }
+
+        let mut line = String::new();
+        write!(
+            line,
+            "{:>10}  {:04X}  {:04X}  {:<20}  V=",
+            cycle,
+            registers.pc,
+            opcode,
+            decode(opcode).to_string()
+        )
+        .unwrap();
+
+        for value in registers.v {
+            write!(line, "{:02X}", value).unwrap();
+        }
+
+        write!(
+            line,
+            "  I={:04X}  SP={:X}  DT={:02X}  ST={:02X}",
+            registers.i, registers.sp, registers.delay_timer, registers.sound_timer
+        )
+        .unwrap();
+
+        match &mut self.ring {
+            Some(ring) => {
+                if ring.len() == self.ring_size {
+                    ring.pop_front();
+                }
+                ring.push_back(line);
+            }
+            None => self.write(&line),
+        }
+    }
+
+    /// Writes out the kept lines, if any, followed by the error.
+    pub fn error(&mut self, error: &Chip8Error) {
+        if let Some(ring) = self.ring.take() {
+            for line in &ring {
+                self.write(line);
+            }
+
+            self.ring = Some(VecDeque::with_capacity(self.ring_size));
+        }
+
+        self.write(&format!("error: {}", error));
+    }
+
+    pub fn flush(&self) {
+        self.output.lock().unwrap().flush().ok();
+    }
+
+    fn write(&self, line: &str) {
+        writeln!(self.output.lock().unwrap(), "{}", line).ok();
+    }
+}
+
+/// Parses a `start-end` range of hex addresses.
+pub fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let error = || format!("Invalid address range: {}", text);
+    let address = |s: &str| {
+        let digits = s.trim_start_matches("0x").trim_start_matches('$');
+        u16::from_str_radix(digits, 16).map_err(|_| error())
+    };
+
+    let (start, end) = text.split_once('-').ok_or_else(error)?;
+    let (start, end) = (address(start)?, address(end)?);
+
+    if end < start {
+        return Err(error());
+    }
+
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output() -> Arc<Mutex<Vec<u8>>> {
+        Arc::new(Mutex::new(vec![]))
+    }
+
+    fn lines(output: &Arc<Mutex<Vec<u8>>>) -> Vec<String> {
+        String::from_utf8(output.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn machine(rom: &[u8], tracer: Tracer) -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(rom).unwrap();
+        cpu.start_tracing(tracer);
+        cpu
+    }
+
+    #[test]
+    fn logs_each_instruction() {
+        let output = output();
+        let mut cpu = machine(
+            &[0x60, 0x05, 0xA3, 0x00],
+            Tracer::new(output.clone(), None, 0),
+        );
+
+        cpu.emulate_cycle().unwrap();
+        cpu.emulate_cycle().unwrap();
+
+        assert_eq!(
+            lines(&output),
+            [
+                "         0  0200  6005  LD V0, 0x05           \
+                 V=00000000000000000000000000000000  I=0000  SP=0  DT=00  ST=00",
+                "         1  0202  A300  LD I, 0x300           \
+                 V=05000000000000000000000000000000  I=0000  SP=0  DT=00  ST=00",
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_by_address() {
+        let output = output();
+        let tracer = Tracer::new(output.clone(), Some(0x202..=0x203), 0);
+        let mut cpu = machine(&[0x60, 0x05, 0x61, 0x06, 0x62, 0x07], tracer);
+
+        for _ in 0..3 {
+            cpu.emulate_cycle().unwrap();
+        }
+
+        let lines = lines(&output);
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].starts_with("         1  0202  6106"),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    fn ring_is_written_on_error() {
+        let output = output();
+        // 0x200: LD V0, 1; LD V1, 2; LD V2, 3; RET
+        let rom = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x00, 0xEE];
+        let mut cpu = machine(&rom, Tracer::new(output.clone(), None, 2));
+
+        for _ in 0..3 {
+            cpu.emulate_cycle().unwrap();
+        }
+        assert!(lines(&output).is_empty());
+
+        assert!(cpu.emulate_cycle().is_err());
+        let lines = lines(&output);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("LD V2, 0x03"), "{}", lines[0]);
+        assert!(lines[1].contains("RET"), "{}", lines[1]);
+        assert_eq!(lines[2], "error: Stack underflow by 00EE at 0x206");
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("0x200-0x2ff"), Ok(0x200..=0x2FF));
+        assert_eq!(parse_range("300-300"), Ok(0x300..=0x300));
+        assert!(parse_range("0x300-0x200").is_err());
+        assert!(parse_range("0x300").is_err());
+    }
+}
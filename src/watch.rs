@@ -0,0 +1,86 @@
+use std::fmt;
+
+use crate::instruction::decode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "Read"),
+            Access::Write => write!(f, "Write"),
+            Access::Execute => write!(f, "Execute"),
+        }
+    }
+}
+
+/// Pauses the machine after any instruction that accesses a byte in
+/// `start..=end` in one of the enabled ways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, address: u16, access: Access) -> bool {
+        let enabled = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+
+        enabled && (self.start..=self.end).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06X}", self.start)?;
+
+        if self.end != self.start {
+            write!(f, "-{:#06X}", self.end)?;
+        }
+
+        write!(f, " ")?;
+
+        for (enabled, flag) in [(self.read, 'r'), (self.write, 'w'), (self.execute, 'x')] {
+            if enabled {
+                write!(f, "{}", flag)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The first watched access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub access: Access,
+    pub address: u16,
+    pub pc: u16,
+    pub opcode: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {:#06X} by {:04X} ({}) at {:#06X}",
+            self.access,
+            self.address,
+            self.opcode,
+            decode(self.opcode),
+            self.pc
+        )
+    }
+}
@@ -0,0 +1,118 @@
+//! Runs the bundled test ROMs headlessly and compares the final display
+//! against the PBM images in `tests/golden`. Set `UPDATE_GOLDEN=1` to
+//! rewrite the images after an intended change.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FRAMES: u32 = 60;
+
+fn run_rom(name: &str) -> Vec<u8> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let rom = root.join("roms").join(format!("{}.ch8", name));
+    let screenshot = env::temp_dir().join(format!(
+        "chip8-conformance-{}-{}.pbm",
+        name,
+        std::process::id()
+    ));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg("run")
+        .arg(&rom)
+        .arg("--headless")
+        .arg(format!("--frames={}", FRAMES))
+        .arg("--seed=1")
+        .arg(format!("--screenshot={}", screenshot.display()))
+        .output()
+        .expect("failed to start the emulator");
+
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        name,
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let image = fs::read(&screenshot).expect("no screenshot written");
+    fs::remove_file(&screenshot).ok();
+    image
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.pbm", name))
+}
+
+/// Renders a P4 image as rows of `#` and `.` so mismatches are readable.
+fn ascii(image: &[u8]) -> String {
+    let mut parts = image.splitn(3, |&b| b == b'\n');
+    parts.next();
+    let size = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_string();
+    let pixels = parts.next().unwrap_or_default();
+    let width = size
+        .split_whitespace()
+        .next()
+        .and_then(|w| w.parse::<usize>().ok())
+        .unwrap_or(64);
+
+    pixels
+        .chunks(width.div_ceil(8))
+        .map(|row| {
+            (0..width)
+                .map(|x| {
+                    if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn check(name: &str) {
+    let actual = run_rom(name);
+    let path = golden_path(name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read(&path).unwrap_or_else(|_| panic!("missing {}", path.display()));
+
+    assert!(
+        actual == expected,
+        "{} does not match {}\nexpected:\n{}\nactual:\n{}",
+        name,
+        path.display(),
+        ascii(&expected),
+        ascii(&actual)
+    );
+}
+
+#[test]
+fn test_rom() {
+    check("test");
+}
+
+#[test]
+fn test_rom_with_audio() {
+    check("chip8-test-rom-with-audio");
+}
+
+#[test]
+fn ibm_logo() {
+    check("ibm");
+}
+
+#[test]
+fn chip8_logo() {
+    check("chip8");
+}